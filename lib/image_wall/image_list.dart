@@ -55,7 +55,9 @@ mixin ImageListPage {
                             ),
                           ),
                         )
-                      : Image.file(File(img.url)),
+                      : Image.file(
+                          File(img.thumbnail.isEmpty ? img.url : img.thumbnail),
+                        ),
                 ),
                 onTap: () {
                   if (img.url.isNotEmpty) {
@@ -23,6 +23,7 @@ class _ImageViewerState extends State<ImageViewer> {
       WallpaperMode.center => Icons.zoom_in_map,
       WallpaperMode.tile => Icons.grid_view,
       WallpaperMode.crop => Icons.crop,
+      WallpaperMode.auto => Icons.auto_awesome,
     });
   }
 
@@ -62,6 +63,7 @@ class _ImageViewerState extends State<ImageViewer> {
                           selected: WallpaperSelection(
                             path: widget.img.url,
                             mode: selectedMode,
+                            monitor: null,
                           ),
                         ).sendSignalToRust();
                       }
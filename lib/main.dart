@@ -58,21 +58,48 @@ class MyHomePage extends StatefulWidget {
 class _MyHomePageState extends State<MyHomePage> {
   ImageService _selectedSource = ImageService.bing;
 
-  void _onItemTapped(int index) {
+  @override
+  void initState() {
+    super.initState();
+    // the response triggers a rebuild, so this must not be sent from `build`
+    GetSettings().sendSignalToRust();
+  }
+
+  void _onItemTapped(ImageService service) {
     setState(() {
-      _selectedSource = ImageService.values[index];
+      _selectedSource = service;
     });
   }
 
   @override
   Widget build(BuildContext context) {
+    return StreamBuilder(
+        stream: SettingsState.rustSignalStream,
+        builder: (context, snapshot) {
+          // the hub lists all services, in their display order
+          final services = snapshot.hasData
+              ? snapshot.data!.message.settings.services
+                  .where((entry) => entry.enabled)
+                  .map((entry) => entry.service)
+                  .toList()
+              : ImageService.values;
+          // the selected service may have been disabled
+          final selected = services.contains(_selectedSource)
+              ? _selectedSource
+              : services.firstOrNull;
+          return _buildPage(context, services, selected);
+        });
+  }
+
+  Widget _buildPage(BuildContext context, List<ImageService> services,
+      ImageService? selected) {
     final colorScheme = Theme.of(context).colorScheme;
     var drawerDestinations = <Widget>[];
-    for (final (i, item) in ImageService.values.indexed) {
+    for (final item in services) {
       drawerDestinations.add(ListTile(
         title: Text(getServiceName(item)),
         onTap: () {
-          _onItemTapped(i);
+          _onItemTapped(item);
           Navigator.pop(context);
         },
       ));
@@ -80,7 +107,9 @@ class _MyHomePageState extends State<MyHomePage> {
 
     return Scaffold(
       appBar: AppBar(
-        title: Text(getServiceName(_selectedSource)),
+        title: Text(selected != null
+            ? getServiceName(selected)
+            : 'Daily Wallpaper Images'),
         leading: Builder(
           builder: (context) {
             return IconButton(
@@ -155,8 +184,12 @@ class _MyHomePageState extends State<MyHomePage> {
               drawerDestinations,
         ),
       ),
-      body: ImageWall(service: _selectedSource),
-      floatingActionButton: _selectedSource == ImageService.spotlight
+      body: selected != null
+          ? ImageWall(service: selected)
+          : Center(
+              child: Text("No image sources are enabled"),
+            ),
+      floatingActionButton: selected == ImageService.spotlight
           ? FloatingActionButton.small(
               onPressed: () {
                 Refresh(service: ImageService.spotlight, reset: true)
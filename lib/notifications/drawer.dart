@@ -19,7 +19,7 @@ class NotificationCenter extends StatelessWidget {
                           .map((e) => NotificationBubble(
                                 e.value,
                                 onTap: () =>
-                                    NotificationDismiss(timestamp: e.key)
+                                    NotificationDismiss(id: e.value.id)
                                         .sendSignalToRust(),
                               )),
                     ),
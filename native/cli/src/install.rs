@@ -0,0 +1,248 @@
+//! Install (or uninstall) entries that run the headless refresh-and-set command automatically,
+//! so the wallpaper changes without the app (or the daemon) running.
+//!
+//! A systemd user timer runs the command daily at a chosen time.
+//! An XDG autostart entry runs the command whenever the user logs in.
+#![cfg(target_os = "linux")]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// The name shared by all installed files.
+const ENTRY_NAME: &str = "daily-wallpaper";
+
+/// The kinds of entries that can be installed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Entry {
+    /// A systemd user service and timer.
+    Systemd,
+    /// An XDG autostart desktop entry.
+    Autostart,
+}
+
+impl Entry {
+    /// The files written when installing this kind of entry.
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let config_dir = dirs::config_dir().ok_or(anyhow!("Failed to find user config folder"))?;
+        Ok(match self {
+            Entry::Systemd => {
+                let units_dir = config_dir.join("systemd").join("user");
+                vec![
+                    units_dir.join(format!("{ENTRY_NAME}.service")),
+                    units_dir.join(format!("{ENTRY_NAME}.timer")),
+                ]
+            }
+            Entry::Autostart => vec![config_dir
+                .join("autostart")
+                .join(format!("{ENTRY_NAME}.desktop"))],
+        })
+    }
+}
+
+/// Quote an argument for the `ExecStart` of a systemd unit.
+///
+/// Quotes and backslashes are escaped with a backslash,
+/// while `$` (variables) and `%` (specifiers) are escaped by doubling them.
+fn quote_for_systemd(arg: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '$' => quoted.push_str("$$"),
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quote an argument for the `Exec` of a desktop entry.
+///
+/// Quotes, backticks, `$` and backslashes are escaped with a backslash,
+/// and `%` (field codes) is escaped by doubling it.
+/// The whole value is unescaped once more as a string, so each of those backslashes is doubled.
+fn quote_for_desktop_entry(arg: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        match c {
+            '"' | '`' | '$' => {
+                quoted.push_str("\\\\");
+                quoted.push(c);
+            }
+            '\\' => quoted.push_str("\\\\\\\\"),
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Run `systemctl --user` with the given `args`.
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .with_context(|| "Failed to run systemctl")?;
+    if !status.success() {
+        bail!("`systemctl --user {}` failed ({status})", args.join(" "));
+    }
+    Ok(())
+}
+
+/// Get the files of all installed entries.
+pub fn find_installed() -> Result<Vec<PathBuf>> {
+    let mut installed = vec![];
+    for entry in [Entry::Systemd, Entry::Autostart] {
+        installed.extend(entry.files()?.into_iter().filter(|f| f.exists()));
+    }
+    Ok(installed)
+}
+
+/// Install an `entry` that runs this executable with the given `args`.
+///
+/// The `time` (hours and minutes) is only used by [`Entry::Systemd`].
+/// This fails if any entry is already installed; use [`uninstall()`] to replace it.
+pub fn install(entry: Entry, args: &[String], time: (u8, u8)) -> Result<Vec<PathBuf>> {
+    let installed = find_installed()?;
+    if !installed.is_empty() {
+        let files = installed
+            .iter()
+            .map(|f| format!("  {}", f.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        bail!("Already installed:\n{files}\nRun `{ENTRY_NAME} uninstall` first to replace it.");
+    }
+    let exe = std::env::current_exe().with_context(|| "Failed to find the path to this program")?;
+    let quote = match entry {
+        Entry::Systemd => quote_for_systemd,
+        Entry::Autostart => quote_for_desktop_entry,
+    };
+    let command = [exe.to_string_lossy().to_string()]
+        .iter()
+        .chain(args)
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let files = entry.files()?;
+    let contents = match entry {
+        Entry::Systemd => vec![
+            format!(
+                "[Unit]\n\
+                Description=Set the desktop wallpaper to a daily image\n\
+                \n\
+                [Service]\n\
+                Type=oneshot\n\
+                ExecStart={command}\n"
+            ),
+            format!(
+                "[Unit]\n\
+                Description=Set the desktop wallpaper to a daily image\n\
+                \n\
+                [Timer]\n\
+                OnCalendar=*-*-* {:02}:{:02}:00\n\
+                Persistent=true\n\
+                \n\
+                [Install]\n\
+                WantedBy=timers.target\n",
+                time.0, time.1
+            ),
+        ],
+        Entry::Autostart => vec![format!(
+            "[Desktop Entry]\n\
+            Type=Application\n\
+            Name=Daily Wallpaper Images\n\
+            Comment=Set the desktop wallpaper to a daily image\n\
+            Exec={command}\n\
+            Terminal=false\n\
+            NoDisplay=true\n"
+        )],
+    };
+    let result = write_and_enable(entry, &files, contents);
+    if result.is_err() {
+        // don't leave a partial installation behind, which would block the next attempt
+        for file in files.iter().filter(|f| f.exists()) {
+            let _ = fs::remove_file(file);
+        }
+        if entry == Entry::Systemd {
+            let _ = systemctl(&["daemon-reload"]);
+        }
+    }
+    result.map(|_| files)
+}
+
+/// Write the `contents` of an `entry`'s `files`, and enable it if needed.
+fn write_and_enable(entry: Entry, files: &[PathBuf], contents: Vec<String>) -> Result<()> {
+    for (file, content) in files.iter().zip(contents) {
+        write_file(file, &content)?;
+    }
+    if entry == Entry::Systemd {
+        systemctl(&["daemon-reload"])?;
+        systemctl(&["enable", "--now", &format!("{ENTRY_NAME}.timer")])?;
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create folder {}", parent.to_string_lossy()))?;
+    }
+    fs::write(path, content).with_context(|| format!("Failed to write {}", path.to_string_lossy()))
+}
+
+/// Remove all installed entries.
+///
+/// Returns the removed files.
+pub fn uninstall() -> Result<Vec<PathBuf>> {
+    let installed = find_installed()?;
+    let systemd_files = Entry::Systemd.files()?;
+    let has_systemd = installed.iter().any(|f| systemd_files.contains(f));
+    if has_systemd {
+        systemctl(&["disable", "--now", &format!("{ENTRY_NAME}.timer")])?;
+    }
+    for file in &installed {
+        fs::remove_file(file)
+            .with_context(|| format!("Failed to remove {}", file.to_string_lossy()))?;
+    }
+    if has_systemd {
+        systemctl(&["daemon-reload"])?;
+    }
+    Ok(installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quote_for_desktop_entry, quote_for_systemd};
+
+    #[test]
+    fn quotes_for_systemd() {
+        assert_eq!(quote_for_systemd("/usr/bin/daily"), r#""/usr/bin/daily""#);
+        assert_eq!(
+            quote_for_systemd(r#"a "b" \c $HOME 100% `d`"#),
+            r#""a \"b\" \\c $$HOME 100%% `d`""#
+        );
+    }
+
+    #[test]
+    fn quotes_for_desktop_entry() {
+        assert_eq!(
+            quote_for_desktop_entry("/opt/Daily Wallpaper/daily"),
+            r#""/opt/Daily Wallpaper/daily""#
+        );
+        assert_eq!(
+            quote_for_desktop_entry(r#"a "b" \c $HOME 100% `d`"#),
+            r#""a \\"b\\" \\\\c \\$HOME 100%% \\`d\\`""#
+        );
+    }
+}
@@ -0,0 +1,736 @@
+//! A command-line interface to the hub, for systems without the Flutter UI
+//! (e.g. minimal window managers, kiosks or cron jobs).
+
+#[cfg(target_os = "linux")]
+mod install;
+
+use std::{
+    io::{stderr, IsTerminal, Write},
+    path::{Path, PathBuf},
+    pin::pin,
+    process::ExitCode,
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+#[cfg(unix)]
+use hub::control::{self, ControlRequest, ControlResponse};
+use hub::{
+    headless::{self, Hub},
+    services::{list_cached_images, Prune},
+    signals::{
+        ExportArchive, GetSettings, ImageService, ImportArchive, ImportConflict, ListMonitors,
+        NotificationAlert, NotificationSeverity, Refresh, RevertWallpaper, SetWallpaper,
+        WallpaperHistoryRefresh, WallpaperMode, WallpaperSelection,
+    },
+};
+#[cfg(unix)]
+use tokio::signal::{
+    ctrl_c,
+    unix::{signal, SignalKind},
+};
+use tokio::{select, sync::mpsc::unbounded_channel};
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Fetch the latest images from a service.
+    Refresh {
+        service: Service,
+        /// Fetch the service's metadata even if it was already cached today.
+        #[arg(long)]
+        reset: bool,
+        /// Set the desktop wallpaper to the newest image afterward.
+        #[arg(long)]
+        set: bool,
+        /// How the image is fitted to the screen (with `--set`).
+        #[arg(long, requires = "set")]
+        mode: Option<Mode>,
+    },
+    /// List the cached images of a service (newest first).
+    List { service: Service },
+    /// Set the desktop wallpaper.
+    Set {
+        /// The path to an image, or `latest` to use the newest cached image of `--service`.
+        image: String,
+        /// How the image is fitted to the screen.
+        ///
+        /// If not specified, then the desktop's current mode is kept.
+        #[arg(long)]
+        mode: Option<Mode>,
+        /// The service to take the `latest` image from.
+        #[arg(long, default_value = "bing")]
+        service: Service,
+        /// Only set the wallpaper on this monitor (see the `monitors` command).
+        #[arg(long)]
+        monitor: Option<String>,
+    },
+    /// List the monitors connected to the desktop.
+    Monitors,
+    /// List the recent wallpaper changes (newest first).
+    History,
+    /// Restore the wallpaper that was set before the current one.
+    Revert {
+        /// Revert the wallpaper of this monitor, instead of one set on all monitors.
+        #[arg(long)]
+        monitor: Option<String>,
+    },
+    /// Write the settings and wallpaper history into an archive (a tar file).
+    Export {
+        /// Where the archive is written.
+        path: PathBuf,
+        /// Also include the cached images.
+        #[arg(long)]
+        images: bool,
+    },
+    /// Merge an archive written by the `export` command into the cache.
+    Import {
+        path: PathBuf,
+        /// Replace existing settings and files with the imported ones, instead of keeping them.
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Remove cached files that are no longer listed by a service.
+    Prune {
+        /// The services to prune. Defaults to all services.
+        services: Vec<Service>,
+    },
+    /// Run in the background, changing the wallpaper on schedule and
+    /// listening for commands from other processes.
+    #[cfg(unix)]
+    Daemon,
+    /// Show the slideshow's next image (requires a running daemon).
+    #[cfg(unix)]
+    Next,
+    /// Show the slideshow's previous image (requires a running daemon).
+    #[cfg(unix)]
+    Previous,
+    /// Show the running daemon's status.
+    #[cfg(unix)]
+    Status,
+    /// Refresh and set the wallpaper automatically, without the app or the daemon running.
+    ///
+    /// By default, this installs a systemd user timer that runs daily.
+    #[cfg(target_os = "linux")]
+    Install {
+        /// The service to take images from.
+        #[arg(long, default_value = "bing")]
+        service: Service,
+        /// How the image is fitted to the screen.
+        #[arg(long)]
+        mode: Option<Mode>,
+        /// The time of day (`HH:MM`) to run the systemd timer.
+        #[arg(long, default_value = "08:00", value_parser = parse_time)]
+        at: (u8, u8),
+        /// Install an XDG autostart entry (run on login) instead of a systemd timer.
+        #[arg(long)]
+        autostart: bool,
+    },
+    /// Remove the entries written by the `install` command.
+    #[cfg(target_os = "linux")]
+    Uninstall,
+}
+
+/// Parse a time of day in the form `HH:MM`.
+#[cfg(target_os = "linux")]
+fn parse_time(value: &str) -> Result<(u8, u8), String> {
+    value
+        .split_once(':')
+        .and_then(|(hour, minute)| Some((hour.parse().ok()?, minute.parse().ok()?)))
+        .filter(|(hour, minute)| *hour < 24 && *minute < 60)
+        .ok_or(format!("expected a time of day like 08:00, not {value}"))
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Service {
+    Bing,
+    Nasa,
+    Spotlight,
+}
+
+impl From<Service> for ImageService {
+    fn from(value: Service) -> Self {
+        match value {
+            Service::Bing => ImageService::Bing,
+            Service::Nasa => ImageService::Nasa,
+            Service::Spotlight => ImageService::Spotlight,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Mode {
+    Center,
+    Crop,
+    Fit,
+    Stretch,
+    Tile,
+    /// Pick a mode based on the image's size and the screen's aspect ratio.
+    Auto,
+}
+
+impl From<Mode> for WallpaperMode {
+    fn from(value: Mode) -> Self {
+        match value {
+            Mode::Center => WallpaperMode::Center,
+            Mode::Crop => WallpaperMode::Crop,
+            Mode::Fit => WallpaperMode::Fit,
+            Mode::Stretch => WallpaperMode::Stretch,
+            Mode::Tile => WallpaperMode::Tile,
+            Mode::Auto => WallpaperMode::Auto,
+        }
+    }
+}
+
+/// Print a notification that would otherwise be shown in the app.
+///
+/// On a terminal, progress is shown on a single line that is continuously updated.
+/// Otherwise (e.g. when logging from a cron job), only finished notifications are printed.
+/// Errors are not printed here because they are also returned by the failed command.
+fn print_progress(alert: &NotificationAlert) {
+    if let Some(text) = format_progress(alert, stderr().is_terminal()) {
+        eprint!("{text}");
+        let _ = stderr().flush();
+    }
+}
+
+/// Format a notification for [`print_progress()`], or get `None` if it is not printed.
+fn format_progress(alert: &NotificationAlert, is_terminal: bool) -> Option<String> {
+    if matches!(alert.severity, NotificationSeverity::Error) {
+        return None;
+    }
+    if alert.percent < 1.0 {
+        return is_terminal.then(|| {
+            format!(
+                "\r\x1b[2K[{:>3.0}%] {}: {}",
+                alert.percent * 100.0,
+                alert.title,
+                alert.body
+            )
+        });
+    }
+    let status = &alert.status;
+    let details = [
+        status
+            .downloaded
+            .as_ref()
+            .map(|d| format!("downloaded {d}")),
+        status.removed.map(|r| format!("removed {r}")),
+        status.elapsed.as_ref().map(|e| format!("took {e}")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    let clear = if is_terminal { "\r\x1b[2K" } else { "" };
+    Some(if details.is_empty() {
+        format!("{clear}{}: {}\n", alert.title, alert.body)
+    } else {
+        format!(
+            "{clear}{}: {} ({})\n",
+            alert.title,
+            alert.body,
+            details.join(", ")
+        )
+    })
+}
+
+/// Print a notification from the daemon's actors.
+///
+/// Unlike [`print_progress()`], this prints errors as well, because there is no command
+/// that would return them.
+#[cfg(unix)]
+fn log_alert(alert: &NotificationAlert) {
+    if alert.percent >= 1.0 {
+        eprintln!("{:?}: {}: {}", alert.severity, alert.title, alert.body);
+    }
+}
+
+/// Send a `request` to the running daemon and print its response.
+#[cfg(unix)]
+async fn run_remote(socket_path: &Path, request: ControlRequest) -> Result<()> {
+    match control::send_request(socket_path, &request).await? {
+        ControlResponse::Done => Ok(()),
+        ControlResponse::Error(e) => Err(anyhow!(e)),
+        ControlResponse::Status(status) => {
+            let slideshow = &status.slideshow;
+            if slideshow.enabled {
+                println!(
+                    "Slideshow: every {} minutes ({:?} order)",
+                    slideshow.interval, slideshow.order
+                );
+            } else {
+                println!("Slideshow: stopped");
+            }
+            if let Some(current) = &status.current {
+                println!("Current image: {current}");
+            }
+            let schedule = &status.schedule;
+            if schedule.enabled {
+                println!(
+                    "Scheduled refresh: daily at {:02}:{:02}",
+                    schedule.hour, schedule.minute
+                );
+            } else {
+                println!("Scheduled refresh: disabled");
+            }
+            let day_cycle = &status.day_cycle;
+            if day_cycle.enabled {
+                let slots = day_cycle
+                    .slots
+                    .iter()
+                    .map(|slot| format!("{} at {:02}:{:02}", slot.name, slot.hour, slot.minute))
+                    .collect::<Vec<_>>();
+                println!("Day cycle: {}", slots.join(", "));
+            } else {
+                println!("Day cycle: disabled");
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_install(service: Service, mode: Option<Mode>, at: (u8, u8), autostart: bool) -> Result<()> {
+    let value_name = |value: Option<clap::builder::PossibleValue>| {
+        value.expect("no skipped values").get_name().to_string()
+    };
+    let mut args = vec![
+        "refresh".to_string(),
+        value_name(service.to_possible_value()),
+        "--set".to_string(),
+    ];
+    if let Some(mode) = mode {
+        args.push("--mode".to_string());
+        args.push(value_name(mode.to_possible_value()));
+    }
+    let entry = if autostart {
+        install::Entry::Autostart
+    } else {
+        install::Entry::Systemd
+    };
+    for file in install::install(entry, &args, at)? {
+        println!("Installed {}", file.to_string_lossy());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn run_uninstall() -> Result<()> {
+    let removed = install::uninstall()?;
+    if removed.is_empty() {
+        println!("Nothing is installed");
+    }
+    for file in removed {
+        println!("Removed {}", file.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Run the daemon until it is interrupted or terminated.
+#[cfg(unix)]
+async fn run_daemon() -> Result<()> {
+    let socket_path = control::get_socket_path()?;
+    // checked before the automatic actors start, so they can't change the wallpaper
+    if control::is_daemon_running(&socket_path).await {
+        return Err(anyhow!(
+            "Another daemon is already listening on {socket_path:?}"
+        ));
+    }
+    let (listener, mut progress) = unbounded_channel();
+    let daemon = headless::create_daemon_actors(listener).await?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    eprintln!(
+        "Listening for commands on {}",
+        socket_path.to_string_lossy()
+    );
+    let result = {
+        let mut server = pin!(control::serve(&socket_path, daemon));
+        loop {
+            select! {
+                Some(alert) = progress.recv() => log_alert(&alert),
+                result = &mut server => break result,
+                _ = ctrl_c() => break Ok(()),
+                _ = terminate.recv() => break Ok(()),
+            }
+        }
+    };
+    // only remove the socket if it was ours
+    if result.is_ok() {
+        let _ = std::fs::remove_file(&socket_path);
+    }
+    result
+}
+
+async fn set_wallpaper(
+    hub: &mut Hub,
+    path: &Path,
+    mode: Option<Mode>,
+    monitor: Option<String>,
+) -> Result<()> {
+    let path = path.to_string_lossy().to_string();
+    let target = monitor
+        .as_ref()
+        .map(|monitor| format!(" on {monitor}"))
+        .unwrap_or_default();
+    let auto = hub
+        .wallpaper
+        .send(SetWallpaper {
+            selected: WallpaperSelection {
+                path: path.clone(),
+                mode: mode.map(WallpaperMode::from),
+                monitor,
+            },
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to set the desktop wallpaper: {e:?}"))??;
+    match auto {
+        Some(auto) => println!("Set the desktop wallpaper{target} to {path} ({auto})"),
+        None => println!("Set the desktop wallpaper{target} to {path}"),
+    }
+    Ok(())
+}
+
+/// Get the path of the `image` given to the `set` command,
+/// which is the newest cached image of the `service` for `latest`.
+async fn resolve_image(app_cache_dir: &Path, image: &str, service: Service) -> Result<PathBuf> {
+    if image != "latest" {
+        return PathBuf::from(image)
+            .canonicalize()
+            .with_context(|| format!("Failed to find image {image}"));
+    }
+    let service: ImageService = service.into();
+    list_cached_images(app_cache_dir, &service)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(anyhow!(
+            "No cached {} images; try the `refresh` command first",
+            service.as_str()
+        ))
+}
+
+async fn run(command: Command, mut hub: Hub) -> Result<()> {
+    match command {
+        Command::Refresh {
+            service,
+            reset,
+            set,
+            mode,
+        } => {
+            let images = hub
+                .image_service
+                .send(Refresh {
+                    service: service.into(),
+                    reset,
+                })
+                .await
+                .map_err(|e| anyhow!("Failed to refresh cache: {e:?}"))??;
+            if set {
+                let newest = images.first().ok_or(anyhow!(
+                    "No {} images were cached",
+                    ImageService::from(service).as_str()
+                ))?;
+                set_wallpaper(&mut hub, newest, mode, None).await?;
+            }
+        }
+        Command::List { service } => {
+            for image in list_cached_images(&hub.app_cache_dir, &service.into()).await? {
+                println!("{}", image.to_string_lossy());
+            }
+        }
+        Command::Set {
+            image,
+            mode,
+            service,
+            monitor,
+        } => {
+            let path = resolve_image(&hub.app_cache_dir, &image, service).await?;
+            set_wallpaper(&mut hub, &path, mode, monitor).await?;
+        }
+        Command::Monitors => {
+            let monitors = hub
+                .wallpaper
+                .send(ListMonitors)
+                .await
+                .map_err(|e| anyhow!("Failed to list monitors: {e:?}"))??;
+            for monitor in monitors {
+                let primary = if monitor.primary { " (primary)" } else { "" };
+                println!(
+                    "{}: {}x{}{primary}",
+                    monitor.name, monitor.width, monitor.height
+                );
+                if let Some(wallpaper) = monitor.wallpaper {
+                    println!("  {wallpaper}");
+                }
+            }
+        }
+        Command::History => {
+            let entries = hub
+                .wallpaper
+                .send(WallpaperHistoryRefresh)
+                .await
+                .map_err(|e| anyhow!("Failed to get wallpaper history: {e:?}"))?;
+            for entry in entries {
+                let target = entry.monitor.as_deref().unwrap_or("all monitors");
+                println!("{} ({target}): {}", entry.set_at, entry.path);
+                if let Some(description) = entry.description {
+                    println!("  {description}");
+                }
+            }
+        }
+        Command::Revert { monitor } => {
+            let reverted = hub
+                .wallpaper
+                .send(RevertWallpaper { monitor })
+                .await
+                .map_err(|e| anyhow!("Failed to revert the desktop wallpaper: {e:?}"))??;
+            for path in reverted.missing {
+                eprintln!("Skipped {path}, which no longer exists");
+            }
+            println!("Reverted the desktop wallpaper to {}", reverted.entry.path);
+        }
+        Command::Export { path, images } => {
+            let count = hub
+                .archive
+                .send(ExportArchive {
+                    path: path.to_string_lossy().to_string(),
+                    include_images: images,
+                })
+                .await
+                .map_err(|e| anyhow!("Failed to export library: {e:?}"))??;
+            println!("Wrote {count} files to {}", path.to_string_lossy());
+        }
+        Command::Import { path, replace } => {
+            let summary = hub
+                .archive
+                .send(ImportArchive {
+                    path: path.to_string_lossy().to_string(),
+                    conflict: if replace {
+                        ImportConflict::UseImported
+                    } else {
+                        ImportConflict::KeepLocal
+                    },
+                })
+                .await
+                .map_err(|e| anyhow!("Failed to import library: {e:?}"))??;
+            println!("{summary}");
+        }
+        Command::Prune { services } => {
+            let services = if services.is_empty() {
+                hub.settings
+                    .send(GetSettings)
+                    .await
+                    .map_err(|e| anyhow!("Failed to get settings: {e:?}"))?
+                    .enabled_services()
+            } else {
+                services.into_iter().map(ImageService::from).collect()
+            };
+            for service in services {
+                let removed = hub
+                    .image_service
+                    .send(Prune { service })
+                    .await
+                    .map_err(|e| anyhow!("Failed to prune cache: {e:?}"))??;
+                println!("Removed {removed} files from {} cache", service.as_str());
+            }
+        }
+        #[cfg(unix)]
+        Command::Daemon | Command::Next | Command::Previous | Command::Status => {
+            unreachable!("handled without creating actors")
+        }
+        #[cfg(target_os = "linux")]
+        Command::Install { .. } | Command::Uninstall => {
+            unreachable!("handled without creating actors")
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    #[cfg(target_os = "linux")]
+    match cli.command {
+        Command::Install {
+            service,
+            mode,
+            at,
+            autostart,
+        } => return exit_code(run_install(service, mode, at, autostart)),
+        Command::Uninstall => return exit_code(run_uninstall()),
+        _ => (),
+    }
+    #[cfg(unix)]
+    {
+        // commands that are handled by (or need) a long-running daemon
+        let result = match get_daemon_request(&cli.command).await {
+            Ok(Some((socket_path, request))) => Some(run_remote(&socket_path, request).await),
+            Ok(None) if matches!(cli.command, Command::Daemon) => Some(run_daemon().await),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        };
+        if let Some(result) = result {
+            return exit_code(result);
+        }
+    }
+    let (listener, mut progress) = unbounded_channel();
+    let result = match headless::create_actors(listener).await {
+        Ok(hub) => {
+            let mut command = pin!(run(cli.command, hub));
+            loop {
+                select! {
+                    Some(alert) = progress.recv() => print_progress(&alert),
+                    result = &mut command => break result,
+                }
+            }
+        }
+        Err(e) => Err(e),
+    };
+    while let Ok(alert) = progress.try_recv() {
+        print_progress(&alert);
+    }
+    exit_code(result)
+}
+
+/// Get the request to send to a running daemon for the given `command`.
+///
+/// Returns `None` if the `command` should run in this process.
+#[cfg(unix)]
+async fn get_daemon_request(command: &Command) -> Result<Option<(PathBuf, ControlRequest)>> {
+    let request = match command {
+        Command::Next => ControlRequest::Next,
+        Command::Previous => ControlRequest::Previous,
+        Command::Status => ControlRequest::Status,
+        Command::Refresh {
+            service,
+            reset,
+            set: false,
+            mode: _,
+        } => ControlRequest::Refresh {
+            service: (*service).into(),
+            reset: *reset,
+        },
+        Command::Set {
+            image,
+            mode,
+            service: _,
+            monitor,
+        } if image != "latest" => ControlRequest::Set {
+            path: PathBuf::from(image)
+                .canonicalize()
+                .with_context(|| format!("Failed to find image {image}"))?
+                .to_string_lossy()
+                .to_string(),
+            mode: mode.map(WallpaperMode::from),
+            monitor: monitor.clone(),
+        },
+        Command::Revert { monitor } => ControlRequest::Revert {
+            monitor: monitor.clone(),
+        },
+        _ => return Ok(None),
+    };
+    let socket_path = control::get_socket_path()?;
+    if control::is_daemon_running(&socket_path).await {
+        return Ok(Some((socket_path, request)));
+    }
+    if matches!(
+        request,
+        ControlRequest::Next | ControlRequest::Previous | ControlRequest::Status
+    ) {
+        return Err(anyhow!(
+            "The daemon is not running; start it with `daily-wallpaper daemon`"
+        ));
+    }
+    // the daemon is not running, so do it in this process instead
+    Ok(None)
+}
+
+fn exit_code(result: Result<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_progress, resolve_image, Service};
+    use hub::signals::{NotificationAlert, NotificationSeverity, NotificationStatus};
+    use std::fs;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_time_of_day() {
+        use super::parse_time;
+        assert_eq!(parse_time("08:05"), Ok((8, 5)));
+        assert_eq!(parse_time("23:59"), Ok((23, 59)));
+        assert!(parse_time("24:00").is_err());
+        assert!(parse_time("12:60").is_err());
+        assert!(parse_time("noon").is_err());
+    }
+
+    #[test]
+    fn formats_progress() {
+        let mut alert = NotificationAlert {
+            id: 1,
+            title: "Refreshing Bing".to_string(),
+            body: "Downloading".to_string(),
+            percent: 0.5,
+            severity: NotificationSeverity::Info,
+            status: NotificationStatus::default(),
+        };
+        assert_eq!(
+            format_progress(&alert, true).as_deref(),
+            Some("\r\x1b[2K[ 50%] Refreshing Bing: Downloading")
+        );
+        // progress is only shown where it can be updated in place
+        assert_eq!(format_progress(&alert, false), None);
+
+        alert.percent = 1.0;
+        alert.body = "Done".to_string();
+        alert.status.downloaded = Some("2 images".to_string());
+        alert.status.removed = Some(1);
+        assert_eq!(
+            format_progress(&alert, false).as_deref(),
+            Some("Refreshing Bing: Done (downloaded 2 images, removed 1)\n")
+        );
+        alert.severity = NotificationSeverity::Error;
+        assert_eq!(format_progress(&alert, true), None);
+    }
+
+    #[tokio::test]
+    async fn resolves_latest_image() {
+        let dir = std::env::temp_dir().join(format!("daily-wallpaper-cli-{}", std::process::id()));
+        let service_dir = dir.join("Bing");
+        fs::create_dir_all(&service_dir).unwrap();
+        let missing = resolve_image(&dir, "latest", Service::Bing).await;
+        for name in [
+            "2025-01-01.jpg",
+            "2025-01-03.jpg",
+            "2025-01-02.jpg",
+            "a.json",
+        ] {
+            fs::write(service_dir.join(name), b"").unwrap();
+        }
+        let latest = resolve_image(&dir, "latest", Service::Bing).await;
+        let other = resolve_image(&dir, "latest", Service::Nasa).await;
+        let path = service_dir.join("2025-01-01.jpg");
+        let given = resolve_image(&dir, &path.to_string_lossy(), Service::Nasa).await;
+        let canonical = path.canonicalize().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(missing.is_err());
+        assert_eq!(latest.unwrap(), service_dir.join("2025-01-03.jpg"));
+        assert!(other.is_err());
+        assert_eq!(given.unwrap(), canonical);
+    }
+}
@@ -0,0 +1,575 @@
+//! Export of the settings, the wallpaper history and (optionally) the cached images
+//! into a single tar archive, and the import of such an archive on another machine.
+//!
+//! This lets a curated collection of images (and how they are used) be shared.
+#![cfg(not(target_os = "android"))]
+
+use std::{
+    fmt::Display,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    common::check_err,
+    notification_center::{NotificationActor, NotificationUpdate},
+    services::{
+        settings::{Settings, SETTINGS_FILE},
+        TEMP_FILE_EXT,
+    },
+    set_wallpaper::{HistoryState, ImportHistory, WallpaperActor, HISTORY_FILE},
+    settings_center::{edit_settings, SettingsActor},
+    signals::{
+        ExportArchive, ImageService, ImportArchive, ImportConflict, NotificationAlert,
+        NotificationSeverity, NotificationStatus,
+    },
+};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Local, Utc};
+use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler};
+use rinf::{debug_print, DartSignal};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{fs, spawn, task::spawn_blocking};
+
+/// The current version of the archive's layout.
+const ARCHIVE_VERSION: u32 = 1;
+
+/// The name of the file (in the archive) that describes the archive.
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// The folder (in the archive) that holds a folder of cached files for each service.
+const IMAGES_DIR: &str = "images";
+
+/// The folder (in the app's cache folder) that an archive is unpacked into while importing it.
+const IMPORT_DIR: &str = "import";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// When the archive was written, formatted as RFC 3339.
+    created: String,
+    /// The app's cache folder on the machine that wrote the archive.
+    ///
+    /// Paths in the settings and history that point into this folder
+    /// are moved to the importing machine's cache folder.
+    app_cache_dir: PathBuf,
+    /// Whether the archive contains the cached images.
+    images: bool,
+}
+
+/// What was done with the contents of an imported archive.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// The number of files that did not exist yet.
+    pub added: usize,
+    /// The number of existing files that were replaced.
+    pub replaced: usize,
+    /// The number of files that were kept as they were.
+    pub skipped: usize,
+    /// The number of wallpaper changes added to the history.
+    pub history: usize,
+}
+
+impl Display for ImportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Added {} files, replaced {}, kept {} and added {} wallpaper changes to the history",
+            self.added, self.replaced, self.skipped, self.history
+        )
+    }
+}
+
+/// Get the `path` in the `to` folder if it is in the `from` folder.
+///
+/// Other paths (e.g. of images picked by the user) are returned unchanged.
+fn move_path(path: &str, from: &Path, to: &Path) -> String {
+    match Path::new(path).strip_prefix(from) {
+        Ok(relative) => to.join(relative).to_string_lossy().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Merge the `imported` settings (as JSON) into the `local` settings key by key.
+///
+/// Nested objects (like the wallpaper of each monitor) are merged too,
+/// so keys that only exist on one side are always kept.
+/// The `conflict` decides about keys that exist on both sides.
+fn merge_settings(local: &mut Value, imported: Value, conflict: ImportConflict) {
+    match (local, imported) {
+        (Value::Object(local), Value::Object(imported)) => {
+            for (key, imported) in imported {
+                match local.get_mut(&key) {
+                    Some(local) => merge_settings(local, imported, conflict),
+                    None => {
+                        local.insert(key, imported);
+                    }
+                }
+            }
+        }
+        (local, imported) => {
+            if conflict == ImportConflict::UseImported {
+                *local = imported;
+            }
+        }
+    }
+}
+
+/// Write an archive to `path` with the settings and history cached in `app_cache_dir`,
+/// and also the cached images if `include_images`.
+///
+/// Returns the number of files in the archive (besides the manifest).
+fn write_archive(app_cache_dir: &Path, path: &Path, include_images: bool) -> Result<usize> {
+    let manifest = serde_json::to_vec(&Manifest {
+        version: ARCHIVE_VERSION,
+        created: Local::now().to_rfc3339(),
+        app_cache_dir: app_cache_dir.to_path_buf(),
+        images: include_images,
+    })
+    .with_context(|| "Failed to convert archive manifest to JSON")?;
+    let file = File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+    let mut builder = tar::Builder::new(file);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().try_into().unwrap_or_default());
+    builder
+        .append_data(&mut header, MANIFEST_FILE, manifest.as_slice())
+        .with_context(|| "Failed to add manifest to archive")?;
+
+    let mut count = 0;
+    for name in [SETTINGS_FILE, HISTORY_FILE] {
+        let cached = app_cache_dir.join(name);
+        if cached.exists() {
+            builder
+                .append_path_with_name(&cached, name)
+                .with_context(|| format!("Failed to add {name} to archive"))?;
+            count += 1;
+        }
+    }
+    if include_images {
+        for service in ImageService::ALL {
+            let service_dir = app_cache_dir.join(service.as_str());
+            if !service_dir.exists() {
+                continue;
+            }
+            let entries =
+                std::fs::read_dir(&service_dir).with_context(|| "Failed to read cache folder")?;
+            for entry in entries {
+                let cached = entry
+                    .with_context(|| "Failed to traverse cache dir")?
+                    .path();
+                // thumbnails (in a sub folder) are generated again when needed
+                if !cached.is_file() || cached.extension().is_some_and(|ext| ext == TEMP_FILE_EXT) {
+                    continue;
+                }
+                let Some(name) = cached.file_name() else {
+                    continue;
+                };
+                let archived = Path::new(IMAGES_DIR).join(service.as_str()).join(name);
+                builder
+                    .append_path_with_name(&cached, &archived)
+                    .with_context(|| format!("Failed to add {cached:?} to archive"))?;
+                count += 1;
+            }
+        }
+    }
+    builder
+        .into_inner()
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("Failed to finish writing {path:?}"))?;
+    Ok(count)
+}
+
+/// Unpack the archive at `path` into the (emptied) `import_dir`.
+fn unpack_archive(path: &Path, import_dir: &Path) -> Result<Manifest> {
+    if import_dir.exists() {
+        std::fs::remove_dir_all(import_dir)
+            .with_context(|| "Failed to remove previously unpacked archive")?;
+    }
+    std::fs::create_dir_all(import_dir).with_context(|| "Failed to create import folder")?;
+    let file = File::open(path).with_context(|| format!("Failed to open {path:?}"))?;
+    // this refuses to write files outside of the `import_dir`
+    tar::Archive::new(file)
+        .unpack(import_dir)
+        .with_context(|| format!("Failed to unpack {path:?}"))?;
+    let manifest = std::fs::read(import_dir.join(MANIFEST_FILE))
+        .with_context(|| "The archive has no manifest; was it exported by this app?")?;
+    let manifest: Manifest = serde_json::from_slice(&manifest)
+        .with_context(|| "Failed to deserialize archive manifest")?;
+    if manifest.version > ARCHIVE_VERSION {
+        bail!(
+            "The archive was written by a newer version of the app (version {})",
+            manifest.version
+        );
+    }
+    Ok(manifest)
+}
+
+/// Check if the files at `a` and `b` have the same contents.
+async fn is_same_file(a: &Path, b: &Path) -> Result<bool> {
+    if fs::metadata(a).await?.len() != fs::metadata(b).await?.len() {
+        return Ok(false);
+    }
+    Ok(fs::read(a).await? == fs::read(b).await?)
+}
+
+pub struct ArchiveActor {
+    app_cache_dir: PathBuf,
+    wallpaper: Address<WallpaperActor>,
+    settings: Address<SettingsActor>,
+    /// The control socket of a running daemon, which would overwrite imported files.
+    daemon: Option<PathBuf>,
+}
+
+impl Actor for ArchiveActor {}
+
+impl ArchiveActor {
+    pub fn new(
+        archive_addr: Address<Self>,
+        app_cache_dir: PathBuf,
+        wallpaper: Address<WallpaperActor>,
+        notification_center: Address<NotificationActor>,
+        settings: Address<SettingsActor>,
+    ) -> Self {
+        spawn(Self::listen_to_export(
+            archive_addr.clone(),
+            notification_center.clone(),
+        ));
+        spawn(Self::listen_to_import(archive_addr, notification_center));
+        Self {
+            app_cache_dir,
+            wallpaper,
+            settings,
+            daemon: None,
+        }
+    }
+
+    async fn listen_to_export(
+        mut archive_addr: Address<Self>,
+        mut notification_center: Address<NotificationActor>,
+    ) {
+        let receiver = ExportArchive::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            let path = dart_signal.message.path.clone();
+            let result = archive_addr
+                .send(dart_signal.message)
+                .await
+                .map_err(|e| anyhow!("Failed to send export request: {e:?}"))
+                .and_then(|result| result);
+            let notification = match result {
+                Ok(count) => NotificationAlert {
+                    id: NotificationAlert::next_id(),
+                    title: "Exported library".to_string(),
+                    body: format!("Wrote {count} files to {path}"),
+                    percent: 1.0,
+                    severity: NotificationSeverity::Info,
+                    status: NotificationStatus::default(),
+                },
+                Err(e) => NotificationAlert {
+                    id: NotificationAlert::next_id(),
+                    title: "Failed to export library".to_string(),
+                    body: format!("{e:?}"),
+                    percent: 1.0,
+                    severity: NotificationSeverity::Error,
+                    status: NotificationStatus::default(),
+                },
+            };
+            Self::notify(&mut notification_center, notification).await;
+        }
+    }
+
+    async fn listen_to_import(
+        mut archive_addr: Address<Self>,
+        mut notification_center: Address<NotificationActor>,
+    ) {
+        let receiver = ImportArchive::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            let result = archive_addr
+                .send(dart_signal.message)
+                .await
+                .map_err(|e| anyhow!("Failed to send import request: {e:?}"))
+                .and_then(|result| result);
+            let notification = match result {
+                Ok(summary) => NotificationAlert {
+                    id: NotificationAlert::next_id(),
+                    title: "Imported library".to_string(),
+                    body: summary.to_string(),
+                    percent: 1.0,
+                    severity: NotificationSeverity::Info,
+                    status: NotificationStatus::default(),
+                },
+                Err(e) => NotificationAlert {
+                    id: NotificationAlert::next_id(),
+                    title: "Failed to import library".to_string(),
+                    body: format!("{e:?}"),
+                    percent: 1.0,
+                    severity: NotificationSeverity::Error,
+                    status: NotificationStatus::default(),
+                },
+            };
+            Self::notify(&mut notification_center, notification).await;
+        }
+    }
+
+    async fn notify(
+        notification_center: &mut Address<NotificationActor>,
+        notification: NotificationAlert,
+    ) {
+        if let Ok(result) = check_err(
+            notification_center
+                .send(NotificationUpdate(notification))
+                .await
+                .map_err(|e| anyhow!("Failed to send notification: {e:?}")),
+        ) {
+            let _ = check_err(result);
+        }
+    }
+
+    /// Copy the `imported` file to `target`,
+    /// unless the `conflict` says to keep an existing (different) file.
+    async fn import_file(
+        imported: &Path,
+        target: &Path,
+        conflict: ImportConflict,
+        summary: &mut ImportSummary,
+    ) -> Result<()> {
+        if target.exists() {
+            if conflict == ImportConflict::KeepLocal || is_same_file(imported, target).await? {
+                summary.skipped += 1;
+                return Ok(());
+            }
+            summary.replaced += 1;
+        } else {
+            summary.added += 1;
+        }
+        fs::copy(imported, target)
+            .await
+            .with_context(|| format!("Failed to import {target:?}"))?;
+        Ok(())
+    }
+
+    /// Merge the unpacked archive in `import_dir` into the app's cache.
+    async fn import(
+        &mut self,
+        import_dir: &Path,
+        manifest: &Manifest,
+        conflict: ImportConflict,
+    ) -> Result<ImportSummary> {
+        let mut summary = ImportSummary::default();
+        let app_cache_dir = self.app_cache_dir.clone();
+        let move_path = |path: &str| move_path(path, &manifest.app_cache_dir, &app_cache_dir);
+
+        // images first, so the imported settings and history can refer to them
+        for service in ImageService::ALL {
+            let imported_dir = import_dir.join(IMAGES_DIR).join(service.as_str());
+            if !imported_dir.exists() {
+                continue;
+            }
+            let service_dir = self.app_cache_dir.join(service.as_str());
+            fs::create_dir_all(&service_dir)
+                .await
+                .with_context(|| "Failed to create cache folder")?;
+            let mut entries = fs::read_dir(&imported_dir)
+                .await
+                .with_context(|| "Failed to read imported images")?;
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .with_context(|| "Failed to traverse imported images")?
+            {
+                let imported = entry.path();
+                if let Some(name) = imported.file_name().filter(|_| imported.is_file()) {
+                    Self::import_file(&imported, &service_dir.join(name), conflict, &mut summary)
+                        .await?;
+                }
+            }
+        }
+
+        let imported = import_dir.join(SETTINGS_FILE);
+        if imported.exists() {
+            let text = fs::read_to_string(&imported)
+                .await
+                .with_context(|| "Failed to read imported settings")?;
+            let mut settings = Settings::from_json(&text)?;
+            settings.validate()?;
+            for path in settings.monitors.values_mut() {
+                *path = move_path(path);
+            }
+            for slot in settings.day_cycle.slots.iter_mut() {
+                slot.path = move_path(&slot.path);
+            }
+            let exists = self.app_cache_dir.join(SETTINGS_FILE).exists();
+            let changed = edit_settings(&mut self.settings, move |local| {
+                if !exists {
+                    *local = settings;
+                    return Ok(());
+                }
+                let mut merged = serde_json::to_value(&*local)
+                    .with_context(|| "Failed to convert settings data to JSON")?;
+                let imported = serde_json::to_value(settings)
+                    .with_context(|| "Failed to convert imported settings to JSON")?;
+                merge_settings(&mut merged, imported, conflict);
+                *local = serde_json::from_value(merged)
+                    .with_context(|| "Failed to merge imported settings")?;
+                Ok(())
+            })
+            .await?;
+            match (changed, exists) {
+                (false, _) => summary.skipped += 1,
+                (true, false) => summary.added += 1,
+                (true, true) => summary.replaced += 1,
+            }
+        }
+
+        let imported = import_dir.join(HISTORY_FILE);
+        if imported.exists() {
+            let text = fs::read_to_string(&imported)
+                .await
+                .with_context(|| "Failed to read imported wallpaper history")?;
+            let mut entries = serde_json::from_str::<HistoryState>(&text)
+                .with_context(|| "Failed to deserialize imported wallpaper history")?
+                .entries;
+            for entry in entries.iter_mut() {
+                entry.path = move_path(&entry.path);
+            }
+            summary.history = self
+                .wallpaper
+                .send(ImportHistory { entries })
+                .await
+                .map_err(|e| anyhow!("Failed to import wallpaper history: {e:?}"))??;
+        }
+        Ok(summary)
+    }
+}
+
+#[async_trait]
+impl Handler<ExportArchive> for ArchiveActor {
+    /// The number of files written to the archive.
+    type Result = Result<usize>;
+
+    async fn handle(&mut self, msg: ExportArchive, _context: &MsgContext<Self>) -> Self::Result {
+        debug_print!("Exporting library to {}", msg.path);
+        let app_cache_dir = self.app_cache_dir.clone();
+        spawn_blocking(move || {
+            write_archive(&app_cache_dir, Path::new(&msg.path), msg.include_images)
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to join archive writer task: {e:?}"))?
+    }
+}
+
+#[async_trait]
+impl Handler<ImportArchive> for ArchiveActor {
+    type Result = Result<ImportSummary>;
+
+    async fn handle(&mut self, msg: ImportArchive, _context: &MsgContext<Self>) -> Self::Result {
+        debug_print!("Importing library from {}", msg.path);
+        if self.daemon.is_some() {
+            bail!("Stop the background service before importing a library");
+        }
+        let import_dir = self.app_cache_dir.join(IMPORT_DIR);
+        let manifest = {
+            let import_dir = import_dir.clone();
+            spawn_blocking(move || unpack_archive(Path::new(&msg.path), &import_dir))
+                .await
+                .map_err(|e| anyhow!("Failed to join archive reader task: {e:?}"))?
+        };
+        let result = match manifest {
+            Ok(manifest) => self.import(&import_dir, &manifest, msg.conflict).await,
+            Err(e) => Err(e),
+        };
+        let _ = check_err(
+            fs::remove_dir_all(&import_dir)
+                .await
+                .with_context(|| "Failed to remove unpacked archive"),
+        );
+        result
+    }
+}
+
+// Creates and spawns the actors in the async system.
+pub async fn create_actors(
+    app_cache_dir: PathBuf,
+    wallpaper_addr: Address<WallpaperActor>,
+    notification_addr: Address<NotificationActor>,
+    settings_addr: Address<SettingsActor>,
+    daemon: Option<PathBuf>,
+) -> Result<Address<ArchiveActor>> {
+    // Create actor contexts.
+    let archive_context = MsgContext::new();
+    let archive_addr = archive_context.address();
+
+    // Spawn actors.
+    let actor = ArchiveActor {
+        daemon,
+        ..ArchiveActor::new(
+            archive_addr.clone(),
+            app_cache_dir,
+            wallpaper_addr,
+            notification_addr,
+            settings_addr,
+        )
+    };
+    spawn(archive_context.run(actor));
+    Ok(archive_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_settings, move_path};
+    use crate::signals::ImportConflict;
+    use serde_json::json;
+    use std::path::Path;
+
+    #[test]
+    fn moves_cached_paths() {
+        let from = Path::new("/home/a/.cache/Daily-Wallpaper-Images");
+        let to = Path::new("/home/b/.cache/Daily-Wallpaper-Images");
+        assert_eq!(
+            move_path(
+                "/home/a/.cache/Daily-Wallpaper-Images/Bing/2025-01-01.jpg",
+                from,
+                to
+            ),
+            "/home/b/.cache/Daily-Wallpaper-Images/Bing/2025-01-01.jpg"
+        );
+        assert_eq!(move_path("/pictures/a.jpg", from, to), "/pictures/a.jpg");
+    }
+    #[test]
+    fn merges_settings_by_key() {
+        let local = json!({
+            "mode": "Fit",
+            "monitors": {"DP-1": "/a.jpg", "HDMI-1": "/b.jpg"},
+            "caption": {"enabled": true},
+        });
+        let imported = json!({
+            "mode": "Fill",
+            "monitors": {"HDMI-1": "/c.jpg", "eDP-1": "/d.jpg"},
+            "caption": {"enabled": false, "size": 12},
+        });
+
+        let mut merged = local.clone();
+        merge_settings(&mut merged, imported.clone(), ImportConflict::KeepLocal);
+        assert_eq!(
+            merged,
+            json!({
+                "mode": "Fit",
+                "monitors": {"DP-1": "/a.jpg", "HDMI-1": "/b.jpg", "eDP-1": "/d.jpg"},
+                "caption": {"enabled": true, "size": 12},
+            })
+        );
+
+        let mut merged = local;
+        merge_settings(&mut merged, imported, ImportConflict::UseImported);
+        assert_eq!(
+            merged,
+            json!({
+                "mode": "Fill",
+                "monitors": {"DP-1": "/a.jpg", "HDMI-1": "/c.jpg", "eDP-1": "/d.jpg"},
+                "caption": {"enabled": false, "size": 12},
+            })
+        );
+    }
+}
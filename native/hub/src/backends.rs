@@ -0,0 +1,294 @@
+//! The ways a wallpaper can be applied to the desktop.
+//!
+//! The desktop environment's own setting (via the `wallpaper` crate) is used by default.
+//! Desktops that it does not cover (like tiling compositors) can use a custom command instead,
+//! and tests can use a dry run that only records what would be set.
+#![cfg(not(target_os = "android"))]
+
+use std::{fs::OpenOptions, io::Write, path::PathBuf, process::Stdio};
+
+use crate::{
+    common::check_err,
+    monitors::set_monitor_wallpaper,
+    signals::{WallpaperBackendConfig, WallpaperBackendKind, WallpaperMode},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use messages::prelude::async_trait;
+use rinf::debug_print;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    process::{Child, Command},
+    spawn,
+    time::{timeout, Duration},
+};
+use wallpaper::{DesktopClient, DesktopWallpaper};
+
+/// How long a wallpaper command may run before it is considered to keep running
+/// (like `swaybg`, which shows the wallpaper until it is stopped).
+const COMMAND_GRACE: Duration = Duration::from_secs(1);
+
+#[async_trait]
+pub trait WallpaperBackend: Send + Sync {
+    /// Set the image at `path` as the wallpaper.
+    ///
+    /// If a `monitor` (name) is given, then only that monitor's wallpaper is changed
+    /// (if the backend allows it).
+    async fn set_wallpaper(
+        &mut self,
+        path: &str,
+        mode: Option<WallpaperMode>,
+        monitor: Option<&str>,
+    ) -> Result<()>;
+
+    /// Stop anything that was left running to show the wallpaper (before switching backends).
+    async fn stop(&mut self) {}
+}
+
+/// Get the backend described by the `config`.
+pub fn create_backend(config: &WallpaperBackendConfig) -> Box<dyn WallpaperBackend> {
+    match config.kind {
+        WallpaperBackendKind::Desktop => Box::new(DesktopBackend),
+        WallpaperBackendKind::Command => Box::new(CommandBackend::new(config.command.clone())),
+        WallpaperBackendKind::DryRun => Box::new(DryRunBackend {
+            log: PathBuf::from(&config.log),
+        }),
+    }
+}
+
+/// Sets the wallpaper with the desktop environment's own setting.
+pub struct DesktopBackend;
+
+#[async_trait]
+impl WallpaperBackend for DesktopBackend {
+    async fn set_wallpaper(
+        &mut self,
+        path: &str,
+        mode: Option<WallpaperMode>,
+        monitor: Option<&str>,
+    ) -> Result<()> {
+        if let Some(monitor) = monitor {
+            return set_monitor_wallpaper(monitor, path, mode).await;
+        }
+        let mode = mode.map(|mode| match mode {
+            WallpaperMode::Center => wallpaper::Mode::Center,
+            // `Auto` is resolved before a wallpaper is set
+            WallpaperMode::Crop | WallpaperMode::Auto => wallpaper::Mode::Crop,
+            WallpaperMode::Fit => wallpaper::Mode::Fit,
+            WallpaperMode::Stretch => wallpaper::Mode::Stretch,
+            WallpaperMode::Tile => wallpaper::Mode::Tile,
+        });
+        let mut client = check_err(
+            DesktopWallpaper::new()
+                .map_err(|e| anyhow!("Failed to init desktop wallpaper client: {e:?}")),
+        )?;
+        client
+            .set_wallpaper(path, mode)
+            .map_err(|e| anyhow!("Failed to set wallpaper {path}: {e:?}"))
+    }
+}
+
+/// Sets the wallpaper by running a user-configured command (e.g. `feh --bg-fill {path}`).
+///
+/// Commands that keep running to show the wallpaper (e.g. `swaybg -i {path}`) are not waited for,
+/// but stopped when the next wallpaper is set.
+pub struct CommandBackend {
+    pub command: String,
+    /// The command that is still running from the last time the wallpaper was set.
+    running: Option<Child>,
+}
+
+impl CommandBackend {
+    pub fn new(command: String) -> Self {
+        Self {
+            command,
+            running: None,
+        }
+    }
+
+    /// Split the command into arguments and replace the placeholders in each of them.
+    ///
+    /// The command is not run by a shell, so a `path` with spaces stays a single argument.
+    /// The `{mode}` is named like the modes of `swaybg` (defaulting to `fill`).
+    fn expand(
+        &self,
+        path: &str,
+        mode: Option<WallpaperMode>,
+        monitor: Option<&str>,
+    ) -> Vec<String> {
+        let mode = match mode {
+            Some(WallpaperMode::Center) => "center",
+            Some(WallpaperMode::Fit) => "fit",
+            Some(WallpaperMode::Stretch) => "stretch",
+            Some(WallpaperMode::Tile) => "tile",
+            Some(WallpaperMode::Crop | WallpaperMode::Auto) | None => "fill",
+        };
+        self.command
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{path}", path)
+                    .replace("{mode}", mode)
+                    .replace("{monitor}", monitor.unwrap_or("*"))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WallpaperBackend for CommandBackend {
+    async fn set_wallpaper(
+        &mut self,
+        path: &str,
+        mode: Option<WallpaperMode>,
+        monitor: Option<&str>,
+    ) -> Result<()> {
+        let args = self.expand(path, mode, monitor);
+        let Some((program, args)) = args.split_first() else {
+            bail!("No command is configured to set the wallpaper");
+        };
+        self.stop().await;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {program}"))?;
+        let mut stderr = child.stderr.take();
+        let Ok(status) = timeout(COMMAND_GRACE, child.wait()).await else {
+            // the command keeps running to show the wallpaper
+            if let Some(stderr) = stderr {
+                let program = program.clone();
+                spawn(async move {
+                    let mut lines = BufReader::new(stderr).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        debug_print!("{program}: {line}");
+                    }
+                });
+            }
+            self.running = Some(child);
+            return Ok(());
+        };
+        let status = status.with_context(|| format!("Failed to wait for {program}"))?;
+        if !status.success() {
+            let mut message = String::new();
+            if let Some(stderr) = &mut stderr {
+                let _ = stderr.read_to_string(&mut message).await;
+            }
+            bail!("Wallpaper command failed ({status}): {}", message.trim());
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        if let Some(mut running) = self.running.take() {
+            let _ = check_err(
+                running
+                    .kill()
+                    .await
+                    .with_context(|| "Failed to stop the previous wallpaper command"),
+            );
+        }
+    }
+}
+
+/// A call recorded by the [`DryRunBackend`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DryRunCall {
+    pub path: String,
+    pub mode: Option<WallpaperMode>,
+    pub monitor: Option<String>,
+}
+
+/// Does not change the wallpaper, but appends each call (as a line of JSON) to the `log` file.
+pub struct DryRunBackend {
+    pub log: PathBuf,
+}
+
+#[async_trait]
+impl WallpaperBackend for DryRunBackend {
+    async fn set_wallpaper(
+        &mut self,
+        path: &str,
+        mode: Option<WallpaperMode>,
+        monitor: Option<&str>,
+    ) -> Result<()> {
+        let call = DryRunCall {
+            path: path.to_string(),
+            mode,
+            monitor: monitor.map(str::to_string),
+        };
+        let mut line =
+            serde_json::to_string(&call).with_context(|| "Failed to serialize dry run call")?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to record dry run call in {:?}", self.log))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandBackend, DryRunBackend, DryRunCall, WallpaperBackend};
+    use crate::common::testing::TempDir;
+    use crate::signals::WallpaperMode;
+
+    #[test]
+    fn command_placeholders() {
+        let backend = CommandBackend::new("swaymsg output {monitor} bg {path} {mode}".to_string());
+        let args = backend.expand("/my pictures/a.jpg", Some(WallpaperMode::Crop), None);
+        assert_eq!(
+            args,
+            ["swaymsg", "output", "*", "bg", "/my pictures/a.jpg", "fill"]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn long_running_command_is_replaced() {
+        use std::path::Path;
+        use tokio::time::{timeout, Duration};
+
+        let mut backend = CommandBackend::new("sleep 30".to_string());
+        let set = backend.set_wallpaper("", None, None);
+        timeout(Duration::from_secs(10), set)
+            .await
+            .expect("a running command should not block setting the wallpaper")
+            .unwrap();
+        let first = backend
+            .running
+            .as_ref()
+            .and_then(|child| child.id())
+            .unwrap();
+        backend.set_wallpaper("", None, None).await.unwrap();
+        assert!(!Path::new(&format!("/proc/{first}")).exists());
+        backend.stop().await;
+        assert!(backend.running.is_none());
+
+        let mut failing = CommandBackend::new("false".to_string());
+        assert!(failing.set_wallpaper("a.jpg", None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn dry_run_records_calls() {
+        let dir = TempDir::new("dry-run");
+        let log = dir.join("calls.jsonl");
+        let mut backend = DryRunBackend { log: log.clone() };
+        backend
+            .set_wallpaper("a.jpg", Some(WallpaperMode::Fit), Some("DP-1"))
+            .await
+            .unwrap();
+        backend.set_wallpaper("b.jpg", None, None).await.unwrap();
+        let text = std::fs::read_to_string(&log).unwrap();
+        let calls = text
+            .lines()
+            .map(|line| serde_json::from_str::<DryRunCall>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].monitor.as_deref(), Some("DP-1"));
+        assert_eq!(calls[1].path, "b.jpg");
+    }
+}
@@ -0,0 +1,292 @@
+//! Render an image's description onto the wallpaper,
+//! so users can tell what they are looking at without opening the app.
+#![cfg(not(target_os = "android"))]
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    services::TEMP_FILE_EXT,
+    signals::{Caption, CaptionCorner},
+};
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use anyhow::{anyhow, Context, Result};
+use image::{codecs::jpeg::JpegEncoder, Rgb, RgbImage};
+
+/// Fonts that are commonly installed, used when no font is configured.
+const DEFAULT_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/liberation-sans/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "C:\\Windows\\Fonts\\segoeui.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+];
+
+/// The image height that [`Caption::font_size`] is relative to.
+const REFERENCE_HEIGHT: f32 = 1080.0;
+
+/// The widest a caption can be, as a fraction of the image's width.
+const MAX_WIDTH: f32 = 0.4;
+
+/// The opacity of the caption's background.
+const BACKGROUND_ALPHA: f32 = 0.5;
+
+/// The JPEG quality of captioned images.
+const JPEG_QUALITY: u8 = 90;
+
+/// Get the first of the `candidates` that exists.
+fn find_font(candidates: &[&str]) -> Result<PathBuf> {
+    candidates
+        .iter()
+        .map(PathBuf::from)
+        .find(|font| font.exists())
+        .ok_or(anyhow!(
+            "Failed to find a font for the caption (looked for {}); \
+            set the path to a TrueType/OpenType font in the caption settings",
+            candidates.join(", ")
+        ))
+}
+
+fn load_font(path: &str) -> Result<FontVec> {
+    let path = if path.is_empty() {
+        find_font(DEFAULT_FONTS)?
+    } else {
+        PathBuf::from(path)
+    };
+    let data = std::fs::read(&path).with_context(|| format!("Failed to read font {path:?}"))?;
+    FontVec::try_from_vec(data).with_context(|| format!("Failed to parse font {path:?}"))
+}
+
+/// Get the width (in pixels) of a single line of `text`.
+fn line_width<F: Font>(font: &impl ScaleFont<F>, text: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Split `text` into lines that are at most `max_width` wide, as measured by `line_width`.
+///
+/// Words that are wider than `max_width` are not split.
+fn wrap(text: &str, max_width: f32, line_width: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{line} {word}")
+            };
+            if !line.is_empty() && line_width(&candidate) > max_width {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+fn blend(pixel: &mut Rgb<u8>, color: [u8; 3], alpha: f32) {
+    for (channel, value) in pixel.0.iter_mut().zip(color) {
+        *channel = (f32::from(*channel) * (1.0 - alpha) + f32::from(value) * alpha).round() as u8;
+    }
+}
+
+/// Get the top left corner of a caption box of `box_size` in the `corner` of an image of `img_size`,
+/// `margin` pixels away from its edges (if the box fits).
+fn place_box(
+    img_size: (f32, f32),
+    box_size: (f32, f32),
+    corner: CaptionCorner,
+    margin: f32,
+) -> (f32, f32) {
+    let left = match corner {
+        CaptionCorner::TopLeft | CaptionCorner::BottomLeft => margin,
+        CaptionCorner::TopRight | CaptionCorner::BottomRight => img_size.0 - margin - box_size.0,
+    };
+    let top = match corner {
+        CaptionCorner::TopLeft | CaptionCorner::TopRight => margin,
+        CaptionCorner::BottomLeft | CaptionCorner::BottomRight => img_size.1 - margin - box_size.1,
+    };
+    (left.max(0.0), top.max(0.0))
+}
+
+/// Draw the `text` onto the `img` as described by the `caption` options.
+fn draw_caption(img: &mut RgbImage, font: &FontVec, text: &str, caption: &Caption) {
+    let (img_width, img_height) = img.dimensions();
+    let size = caption.font_size.max(1) as f32 * img_height as f32 / REFERENCE_HEIGHT;
+    let scaled = font.as_scaled(PxScale::from(size));
+    let lines = wrap(text, img_width as f32 * MAX_WIDTH, |line| {
+        line_width(&scaled, line)
+    });
+    if lines.is_empty() {
+        return;
+    }
+    let [alpha, r, g, b] = caption.color.to_be_bytes();
+    let (color, alpha) = ([r, g, b], f32::from(alpha) / 255.0);
+    // a dark background for light text, and vice versa
+    let luminance = 0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b);
+    let background = if luminance > 127.0 { [0; 3] } else { [255; 3] };
+
+    let padding = size / 2.0;
+    let line_height = scaled.height() + scaled.line_gap();
+    let box_width = lines
+        .iter()
+        .map(|line| line_width(&scaled, line))
+        .fold(0.0, f32::max)
+        + padding * 2.0;
+    let box_height = line_height * lines.len() as f32 + padding * 2.0;
+    let (left, top) = place_box(
+        (img_width as f32, img_height as f32),
+        (box_width, box_height),
+        caption.corner,
+        size,
+    );
+
+    let right = ((left + box_width) as u32).min(img_width);
+    let bottom = ((top + box_height) as u32).min(img_height);
+    for y in top as u32..bottom {
+        for x in left as u32..right {
+            blend(img.get_pixel_mut(x, y), background, BACKGROUND_ALPHA);
+        }
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        let baseline = top + padding + scaled.ascent() + line_height * i as f32;
+        let mut x = left + padding;
+        let mut previous = None;
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                x += scaled.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(scaled.scale(), point(x, baseline));
+            x += scaled.h_advance(id);
+            previous = Some(id);
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i64 + i64::from(gx);
+                let py = bounds.min.y as i64 + i64::from(gy);
+                if (0..i64::from(img_width)).contains(&px)
+                    && (0..i64::from(img_height)).contains(&py)
+                {
+                    blend(
+                        img.get_pixel_mut(px as u32, py as u32),
+                        color,
+                        coverage * alpha,
+                    );
+                }
+            });
+        }
+    }
+}
+
+/// Get a key that only changes if the caption of `text` would be rendered differently,
+/// so that a captioned image can be reused.
+pub fn get_caption_key(text: &str, caption: &Caption) -> String {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    serde_json::to_string(caption)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Render the `text` onto the image at `src` and save the result to `dest`.
+///
+/// This is CPU-bound, so it should be run with `spawn_blocking()`.
+pub fn render_caption(src: &Path, dest: &Path, text: &str, caption: &Caption) -> Result<()> {
+    let font = load_font(&caption.font)?;
+    let mut img = image::open(src)
+        .with_context(|| format!("Failed to decode image {src:?}"))?
+        .to_rgb8();
+    draw_caption(&mut img, &font, text, caption);
+    // write to a temp file first, so an interrupted write never leaves a partial image
+    let mut temp_path = dest.as_os_str().to_owned();
+    temp_path.push(format!(".{TEMP_FILE_EXT}"));
+    let file = std::fs::File::create(&temp_path)
+        .with_context(|| format!("Failed to create captioned image {dest:?}"))?;
+    img.write_with_encoder(JpegEncoder::new_with_quality(file, JPEG_QUALITY))
+        .with_context(|| format!("Failed to encode captioned image {dest:?}"))?;
+    std::fs::rename(&temp_path, dest)
+        .with_context(|| format!("Failed to move captioned image to {dest:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_font, get_caption_key, place_box, wrap};
+    use crate::signals::{Caption, CaptionCorner};
+
+    #[test]
+    fn wraps_words_to_width() {
+        // every character is 1 wide
+        let width = |line: &str| line.chars().count() as f32;
+        assert_eq!(
+            wrap("the quick brown fox\njumps", 10.0, width),
+            ["the quick", "brown fox", "jumps"]
+        );
+        // a long word gets its own line instead of being split
+        assert_eq!(
+            wrap("a extraordinarily b", 5.0, width),
+            ["a", "extraordinarily", "b"]
+        );
+        assert!(wrap(" \n ", 5.0, width).is_empty());
+    }
+
+    #[test]
+    fn places_box_in_corner() {
+        let place = |corner| place_box((100.0, 50.0), (30.0, 10.0), corner, 5.0);
+        assert_eq!(place(CaptionCorner::TopLeft), (5.0, 5.0));
+        assert_eq!(place(CaptionCorner::BottomRight), (65.0, 35.0));
+        // a box wider than the image starts at its left edge
+        assert_eq!(
+            place_box((20.0, 50.0), (30.0, 10.0), CaptionCorner::TopRight, 5.0),
+            (0.0, 5.0)
+        );
+    }
+
+    #[test]
+    fn reports_missing_font() {
+        let error = find_font(&["/missing/a.ttf", "/missing/b.ttf"]).unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("/missing/b.ttf"));
+        assert!(message.contains("caption settings"));
+    }
+
+    #[test]
+    fn key_changes_with_text_and_options() {
+        let caption = Caption::default();
+        let key = get_caption_key("a", &caption);
+        assert_eq!(key, get_caption_key("a", &caption));
+        assert_ne!(key, get_caption_key("b", &caption));
+        let larger = Caption {
+            font_size: caption.font_size + 1,
+            ..caption.clone()
+        };
+        assert_ne!(key, get_caption_key("a", &larger));
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use rinf::debug_print;
 
 pub fn check_err<T>(result: Result<T>) -> Result<T> {
@@ -7,3 +8,62 @@ pub fn check_err<T>(result: Result<T>) -> Result<T> {
     }
     result
 }
+
+/// A source of the current time.
+///
+/// Time-dependent logic should get the time from a `Clock`, so it can be tested with a fixed time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The [`Clock`] that tells the actual system time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Helpers shared by the tests of several modules.
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{
+        ops::Deref,
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    /// A new folder in the system's temporary folder, which is removed (with its contents) when dropped.
+    ///
+    /// The name is unique, so tests running at the same time don't share files.
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> Self {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!(
+                "daily-wallpaper-{name}-{}-{id}",
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+}
@@ -0,0 +1,416 @@
+//! A local control API that lets other processes drive a long-running hub,
+//! like keyboard shortcuts, status bar widgets or the app's UI.
+//!
+//! Requests and responses are exchanged as one JSON object per line over a Unix domain socket.
+#![cfg(all(unix, not(target_os = "android")))]
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    common::check_err,
+    headless::Daemon,
+    notification_center::{NotificationActor, NotificationUpdate},
+    services::{get_app_cache_dir, settings::Settings},
+    settings_center::get_settings,
+    signals::{
+        DayCycle, ImageService, NotificationAlert, NotificationSeverity, NotificationStatus,
+        Refresh, RefreshSchedule, RevertWallpaper, SetWallpaper, Slideshow, SlideshowRefresh,
+        SlideshowState, StartSlideshow, StopSlideshow, UpdateSettings, VerifyCache, WallpaperMode,
+        WallpaperSelection,
+    },
+    slideshow::{NextSlide, PreviousSlide},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use messages::prelude::Address;
+use rinf::{debug_print, DartSignal, RustSignal};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    spawn,
+};
+
+/// The name of the control socket.
+const SOCKET_NAME: &str = "daily-wallpaper.sock";
+
+/// Get the path to the control socket.
+///
+/// This is in the user's runtime folder if there is one (e.g. `$XDG_RUNTIME_DIR`).
+/// Otherwise, the app's cache folder is used.
+pub fn get_socket_path() -> Result<PathBuf> {
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir,
+        None => get_app_cache_dir()?,
+    };
+    Ok(dir.join(SOCKET_NAME))
+}
+
+/// Get the path to the control socket if a daemon is listening on it.
+pub async fn find_daemon() -> Option<PathBuf> {
+    let socket_path = check_err(get_socket_path()).ok()?;
+    is_daemon_running(&socket_path).await.then_some(socket_path)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ControlRequest {
+    /// Show the slideshow's next image.
+    Next,
+    /// Show the slideshow's previous image.
+    Previous,
+    Refresh {
+        service: ImageService,
+        reset: bool,
+    },
+    /// Set the wallpaper to the image at `path`.
+    Set {
+        path: String,
+        mode: Option<WallpaperMode>,
+        /// The monitor to set the wallpaper on (or all monitors if `None`).
+        monitor: Option<String>,
+    },
+    /// Restore the wallpaper that was set before the current one.
+    Revert {
+        monitor: Option<String>,
+    },
+    /// Remove cached images that are broken (e.g. partially downloaded).
+    Verify,
+    StartSlideshow {
+        slideshow: Option<Slideshow>,
+    },
+    StopSlideshow,
+    Status,
+    /// Replace all settings, like [`UpdateSettings`].
+    UpdateSettings {
+        settings: Box<Settings>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub slideshow: Slideshow,
+    /// The path of the image currently shown by the slideshow (if any).
+    pub current: Option<String>,
+    pub schedule: RefreshSchedule,
+    #[serde(default)]
+    pub day_cycle: DayCycle,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ControlResponse {
+    Done,
+    Status(DaemonStatus),
+    Error(String),
+}
+
+/// Send a `request` to the daemon listening on `socket_path` and wait for its response.
+pub async fn send_request(socket_path: &Path, request: &ControlRequest) -> Result<ControlResponse> {
+    let stream = UnixStream::connect(socket_path)
+        .await
+        .with_context(|| format!("Failed to connect to daemon at {socket_path:?}"))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut line =
+        serde_json::to_string(request).with_context(|| "Failed to serialize control request")?;
+    line.push('\n');
+    writer
+        .write_all(line.as_bytes())
+        .await
+        .with_context(|| "Failed to send control request")?;
+    let response = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await
+        .with_context(|| "Failed to read control response")?
+        .ok_or(anyhow!(
+            "The daemon closed the connection without a response"
+        ))?;
+    serde_json::from_str(&response).with_context(|| "Failed to deserialize control response")
+}
+
+/// Send a `request` to the daemon listening on `socket_path`,
+/// and fail if the daemon could not handle it.
+pub(crate) async fn forward(socket_path: &Path, request: ControlRequest) -> Result<()> {
+    match send_request(socket_path, &request).await? {
+        ControlResponse::Error(e) => bail!(e),
+        _ => Ok(()),
+    }
+}
+
+/// Is there a daemon listening on `socket_path`?
+pub async fn is_daemon_running(socket_path: &Path) -> bool {
+    UnixStream::connect(socket_path).await.is_ok()
+}
+
+/// Listen for control requests on `socket_path` and dispatch them to the `daemon`'s actors.
+///
+/// This only returns if the socket fails.
+pub async fn serve(socket_path: &Path, daemon: Daemon) -> Result<()> {
+    let listener = bind(socket_path).await?;
+    debug_print!("Listening for control requests on {socket_path:?}");
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .with_context(|| "Failed to accept control connection")?;
+        spawn(handle_connection(stream, daemon.clone()));
+    }
+}
+
+/// Bind the control socket at `socket_path`,
+/// replacing a stale one left behind by a daemon that is no longer running.
+async fn bind(socket_path: &Path) -> Result<UnixListener> {
+    if socket_path.exists() {
+        if is_daemon_running(socket_path).await {
+            bail!("Another daemon is already listening on {socket_path:?}");
+        }
+        // left behind by a daemon that did not exit cleanly
+        fs::remove_file(socket_path)
+            .await
+            .with_context(|| "Failed to remove stale control socket")?;
+    }
+    UnixListener::bind(socket_path)
+        .with_context(|| format!("Failed to bind control socket at {socket_path:?}"))
+}
+
+async fn handle_connection(stream: UnixStream, mut daemon: Daemon) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(request) => dispatch(&mut daemon, request)
+                .await
+                .unwrap_or_else(|e| ControlResponse::Error(format!("{e:?}"))),
+            Err(e) => ControlResponse::Error(format!("Invalid control request: {e}")),
+        };
+        let Ok(mut response) = check_err(
+            serde_json::to_string(&response)
+                .with_context(|| "Failed to serialize control response"),
+        ) else {
+            break;
+        };
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn dispatch(daemon: &mut Daemon, request: ControlRequest) -> Result<ControlResponse> {
+    debug_print!("Handling control request {request:?}");
+    match request {
+        ControlRequest::Next => daemon
+            .slideshow
+            .send(NextSlide)
+            .await
+            .map_err(|e| anyhow!("Failed to change slideshow image: {e:?}"))??,
+        ControlRequest::Previous => daemon
+            .slideshow
+            .send(PreviousSlide)
+            .await
+            .map_err(|e| anyhow!("Failed to change slideshow image: {e:?}"))??,
+        ControlRequest::Refresh { service, reset } => {
+            daemon
+                .hub
+                .image_service
+                .send(Refresh { service, reset })
+                .await
+                .map_err(|e| anyhow!("Failed to refresh {} cache: {e:?}", service.as_str()))??;
+        }
+        ControlRequest::Set {
+            path,
+            mode,
+            monitor,
+        } => {
+            daemon
+                .hub
+                .wallpaper
+                .send(SetWallpaper {
+                    selected: WallpaperSelection {
+                        path,
+                        mode,
+                        monitor,
+                    },
+                })
+                .await
+                .map_err(|e| anyhow!("Failed to set the desktop wallpaper: {e:?}"))??;
+        }
+        ControlRequest::Revert { monitor } => {
+            daemon
+                .hub
+                .wallpaper
+                .send(RevertWallpaper { monitor })
+                .await
+                .map_err(|e| anyhow!("Failed to revert the desktop wallpaper: {e:?}"))??;
+        }
+        ControlRequest::Verify => daemon
+            .hub
+            .image_service
+            .send(VerifyCache)
+            .await
+            .map_err(|e| anyhow!("Failed to verify image cache: {e:?}"))??,
+        ControlRequest::StartSlideshow { slideshow } => daemon
+            .slideshow
+            .send(StartSlideshow { slideshow })
+            .await
+            .map_err(|e| anyhow!("Failed to start slideshow: {e:?}"))??,
+        ControlRequest::StopSlideshow => daemon
+            .slideshow
+            .send(StopSlideshow)
+            .await
+            .map_err(|e| anyhow!("Failed to stop slideshow: {e:?}"))??,
+        ControlRequest::UpdateSettings { settings } => daemon
+            .hub
+            .settings
+            .send(UpdateSettings {
+                settings: *settings,
+            })
+            .await
+            .map_err(|e| anyhow!("Failed to update settings: {e:?}"))??,
+        ControlRequest::Status => {
+            let SlideshowState { slideshow, current } = daemon
+                .slideshow
+                .send(SlideshowRefresh)
+                .await
+                .map_err(|e| anyhow!("Failed to get slideshow state: {e:?}"))?;
+            let settings = get_settings(&mut daemon.hub.settings).await?;
+            return Ok(ControlResponse::Status(DaemonStatus {
+                slideshow,
+                current,
+                schedule: settings.schedule,
+                day_cycle: settings.day_cycle,
+            }));
+        }
+    }
+    Ok(ControlResponse::Done)
+}
+
+/// Send a `request` (if any) to the daemon, then get the daemon's status.
+async fn request_status(
+    socket_path: &Path,
+    request: Option<ControlRequest>,
+) -> Result<DaemonStatus> {
+    if let Some(request) = request {
+        forward(socket_path, request).await?;
+    }
+    match send_request(socket_path, &ControlRequest::Status).await? {
+        ControlResponse::Status(status) => Ok(status),
+        ControlResponse::Error(e) => bail!(e),
+        ControlResponse::Done => bail!("The daemon did not respond with its status"),
+    }
+}
+
+/// Forward the app's slideshow signals to the daemon listening on `socket_path`.
+///
+/// This is used instead of creating a second scheduler, slideshow and day cycle
+/// in the app's process.
+/// The other actors (including the settings center) forward their changes on their own
+/// (see [`find_daemon()`]).
+pub(crate) fn attach(socket_path: PathBuf, notification_center: Address<NotificationActor>) {
+    spawn(forward_slideshow_signals(socket_path, notification_center));
+}
+
+async fn report_daemon_error(
+    notification_center: &mut Address<NotificationActor>,
+    e: anyhow::Error,
+) {
+    debug_print!("{e:?}");
+    let _ = notification_center
+        .send(NotificationUpdate(NotificationAlert {
+            id: NotificationAlert::next_id(),
+            title: "Background service".to_string(),
+            body: format!("{e:?}"),
+            percent: 1.0,
+            severity: NotificationSeverity::Error,
+            status: NotificationStatus::default(),
+        }))
+        .await;
+}
+
+async fn forward_slideshow_signals(
+    socket_path: PathBuf,
+    mut notification_center: Address<NotificationActor>,
+) {
+    let start = StartSlideshow::get_dart_signal_receiver();
+    let stop = StopSlideshow::get_dart_signal_receiver();
+    let refresh = SlideshowRefresh::get_dart_signal_receiver();
+    loop {
+        let request = tokio::select! {
+            Some(signal) = start.recv() => Some(ControlRequest::StartSlideshow {
+                slideshow: signal.message.slideshow,
+            }),
+            Some(_) = stop.recv() => Some(ControlRequest::StopSlideshow),
+            Some(_) = refresh.recv() => None,
+            else => break,
+        };
+        match request_status(&socket_path, request).await {
+            Ok(status) => SlideshowState {
+                slideshow: status.slideshow,
+                current: status.current,
+            }
+            .send_signal_to_dart(),
+            Err(e) => report_daemon_error(&mut notification_center, e).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bind, forward, is_daemon_running, send_request, ControlRequest, ControlResponse};
+    use crate::common::testing::TempDir;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        spawn,
+    };
+
+    #[tokio::test]
+    async fn requests_round_trip() {
+        let dir = TempDir::new("control");
+        let socket_path = dir.join("control.sock");
+        let listener = bind(&socket_path).await.unwrap();
+        // a daemon that fails to set any wallpaper, and does everything else
+        let daemon = spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (reader, mut writer) = stream.into_split();
+                let Ok(Some(line)) = BufReader::new(reader).lines().next_line().await else {
+                    continue;
+                };
+                let response = match serde_json::from_str(&line).unwrap() {
+                    ControlRequest::Set { path, .. } => ControlResponse::Error(path),
+                    _ => ControlResponse::Done,
+                };
+                let mut line = serde_json::to_string(&response).unwrap();
+                line.push('\n');
+                writer.write_all(line.as_bytes()).await.unwrap();
+            }
+        });
+
+        let set = ControlRequest::Set {
+            path: "a.jpg".to_string(),
+            mode: None,
+            monitor: None,
+        };
+        let response = send_request(&socket_path, &set).await.unwrap();
+        assert!(matches!(response, ControlResponse::Error(path) if path == "a.jpg"));
+        assert!(forward(&socket_path, set).await.is_err());
+        assert!(forward(&socket_path, ControlRequest::Verify).await.is_ok());
+
+        assert!(is_daemon_running(&socket_path).await);
+        assert!(bind(&socket_path).await.is_err());
+        daemon.abort();
+    }
+
+    #[tokio::test]
+    async fn replaces_stale_socket() {
+        let dir = TempDir::new("control-stale");
+        let socket_path = dir.join("control.sock");
+        // a daemon that exits without removing its socket
+        drop(bind(&socket_path).await.unwrap());
+        assert!(socket_path.exists());
+        assert!(!is_daemon_running(&socket_path).await);
+
+        let listener = bind(&socket_path).await.unwrap();
+        assert!(is_daemon_running(&socket_path).await);
+        drop(listener);
+    }
+}
@@ -0,0 +1,503 @@
+//! The day cycle shows different wallpapers at different times of the day
+//! (e.g. a light one during the day and a dark one at night).
+//!
+//! The wallpaper is either switched when each [`TimeSlot`] starts,
+//! or a GNOME dynamic background is written that the desktop switches (and fades) by itself.
+#![cfg(not(target_os = "android"))]
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    common::check_err,
+    notification_center::{NotificationActor, NotificationUpdate},
+    scheduler::{next_run, sleep_until_local},
+    services::{list_cached_images, settings::SettingsChanged, thumbnails::ensure_thumbnail},
+    set_wallpaper::WallpaperActor,
+    settings_center::{get_settings, SettingsActor},
+    signals::{
+        DayCycle, DayCycleOutput, ImageService, NotificationAlert, NotificationSeverity,
+        NotificationStatus, SetWallpaper, SlotImage, TimeSlot, WallpaperSelection,
+    },
+};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Local, NaiveTime};
+use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler, Notifiable};
+use rinf::debug_print;
+use tokio::{fs, spawn, task::spawn_blocking, task::JoinHandle};
+
+/// The name of the folder (in the app's cache folder) that holds the GNOME dynamic background
+/// and copies of the images it shows.
+const DAY_CYCLE_DIR: &str = "day-cycle";
+
+/// The number of seconds in a day.
+const DAY: u32 = 24 * 60 * 60;
+
+/// Tells the [`DayCycleActor`] to apply the current time slot's wallpaper
+/// (or the whole day's GNOME dynamic background).
+pub struct ApplyDayCycle;
+
+/// Get the time of day that the `slot` starts at.
+///
+/// Invalid times are rejected when the settings are saved, so this only fails for
+/// settings that were not validated.
+fn get_slot_time(slot: &TimeSlot) -> Result<NaiveTime> {
+    NaiveTime::from_hms_opt(slot.hour.into(), slot.minute.into(), 0).ok_or(anyhow!(
+        "The time {:02}:{:02} of {} is not a valid time of day",
+        slot.hour,
+        slot.minute,
+        slot.name
+    ))
+}
+
+/// Get the `slots` (with their start times) ordered by their start time.
+///
+/// Of the slots that start at the same time, only the first one is kept.
+fn sorted_slots(slots: &[TimeSlot]) -> Result<Vec<(NaiveTime, &TimeSlot)>> {
+    let mut sorted = slots
+        .iter()
+        .map(|slot| Ok((get_slot_time(slot)?, slot)))
+        .collect::<Result<Vec<_>>>()?;
+    sorted.sort_by_key(|(time, _)| *time);
+    sorted.dedup_by_key(|(time, _)| *time);
+    Ok(sorted)
+}
+
+/// Get the index of the slot (in the `sorted` slots) that is active at the given `time`.
+///
+/// Before the first slot starts, the last slot (from the previous day) is still active.
+fn current_slot(sorted: &[(NaiveTime, &TimeSlot)], time: NaiveTime) -> Option<usize> {
+    sorted
+        .iter()
+        .rposition(|(start, _)| *start <= time)
+        .or(sorted.len().checked_sub(1))
+}
+
+/// Get the average brightness (0 to 255) of the image at `path`.
+///
+/// This is CPU-bound, so it should be run with `spawn_blocking()`.
+fn mean_brightness(path: &Path) -> Result<f32> {
+    let img = image::open(path)
+        .with_context(|| format!("Failed to decode image {path:?}"))?
+        .to_luma8();
+    let sum: u64 = img.pixels().map(|pixel| u64::from(pixel[0])).sum();
+    let count = u64::from(img.width()) * u64::from(img.height());
+    Ok(sum as f32 / count.max(1) as f32)
+}
+
+/// Escape the special characters of XML in `text`.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Get a GNOME dynamic background that shows each image from its time until the next one's.
+///
+/// The `images` must be ordered by time. Each image fades into the next one
+/// over `transition` seconds (at most half of the time that the image is shown).
+fn gnome_background_xml(images: &[(NaiveTime, PathBuf)], transition: u32) -> String {
+    let mut xml = String::from("<background>\n");
+    let Some((start, _)) = images.first() else {
+        xml.push_str("</background>\n");
+        return xml;
+    };
+    // any day in the past works, because the background repeats every day
+    let _ = write!(
+        xml,
+        "  <starttime>\n    <year>2000</year>\n    <month>1</month>\n    <day>1</day>\n    \
+         <hour>{}</hour>\n    <minute>{}</minute>\n    <second>0</second>\n  </starttime>\n",
+        start.format("%-H"),
+        start.format("%-M"),
+    );
+    for (i, (time, path)) in images.iter().enumerate() {
+        let (next_time, next_path) = &images[(i + 1) % images.len()];
+        let since_midnight = |t: &NaiveTime| t.signed_duration_since(NaiveTime::MIN).num_seconds();
+        let gap = match (since_midnight(next_time) - since_midnight(time)).rem_euclid(DAY.into()) {
+            0 => DAY,
+            gap => gap as u32,
+        };
+        let fade = if images.len() > 1 {
+            transition.min(gap / 2)
+        } else {
+            0
+        };
+        let path = escape_xml(&path.to_string_lossy());
+        let _ = write!(
+            xml,
+            "  <static>\n    <duration>{}.0</duration>\n    <file>{path}</file>\n  </static>\n",
+            gap - fade
+        );
+        if fade > 0 {
+            let next_path = escape_xml(&next_path.to_string_lossy());
+            let _ = write!(
+                xml,
+                "  <transition type=\"overlay\">\n    <duration>{fade}.0</duration>\n    \
+                 <from>{path}</from>\n    <to>{next_path}</to>\n  </transition>\n"
+            );
+        }
+    }
+    xml.push_str("</background>\n");
+    xml
+}
+
+pub struct DayCycleActor {
+    app_cache_dir: PathBuf,
+    day_cycle_addr: Address<Self>,
+    wallpaper: Address<WallpaperActor>,
+    notification_center: Address<NotificationActor>,
+    settings: Address<SettingsActor>,
+    /// The average brightness of cached images, so they are only measured once.
+    brightness: HashMap<PathBuf, f32>,
+    timer: Option<JoinHandle<()>>,
+}
+
+impl Actor for DayCycleActor {}
+
+impl DayCycleActor {
+    pub fn new(
+        day_cycle_addr: Address<Self>,
+        app_cache_dir: PathBuf,
+        wallpaper: Address<WallpaperActor>,
+        notification_center: Address<NotificationActor>,
+        settings: Address<SettingsActor>,
+    ) -> Self {
+        Self {
+            app_cache_dir,
+            day_cycle_addr,
+            wallpaper,
+            notification_center,
+            settings,
+            brightness: HashMap::new(),
+            timer: None,
+        }
+    }
+
+    /// (Re)start the timer that applies the `day_cycle`.
+    ///
+    /// A GNOME dynamic background is only applied once.
+    /// If the `day_cycle` is disabled (or a slot's time is invalid),
+    /// then the timer is only stopped.
+    pub(crate) fn restart_timer(&mut self, day_cycle: &DayCycle) {
+        if let Some(timer) = self.timer.take() {
+            timer.abort();
+        }
+        if !day_cycle.enabled || day_cycle.slots.is_empty() {
+            return;
+        }
+        let times = match day_cycle.output {
+            DayCycleOutput::Switch => {
+                let Ok(slots) = check_err(sorted_slots(&day_cycle.slots)) else {
+                    return;
+                };
+                slots.into_iter().map(|(time, _)| time).collect()
+            }
+            DayCycleOutput::GnomeXml => vec![],
+        };
+        self.timer = Some(spawn(Self::run_timer(self.day_cycle_addr.clone(), times)));
+    }
+
+    /// Apply the day cycle now, and again whenever one of the `times` is reached.
+    async fn run_timer(mut day_cycle_addr: Address<Self>, times: Vec<NaiveTime>) {
+        loop {
+            if let Ok(result) = check_err(
+                day_cycle_addr
+                    .send(ApplyDayCycle)
+                    .await
+                    .map_err(|e| anyhow!("Failed to apply day cycle: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+            let now = Local::now();
+            let Some(next) = times.iter().map(|time| next_run(&now, *time)).min() else {
+                break;
+            };
+            debug_print!("Next day cycle change at {next}");
+            sleep_until_local(next).await;
+        }
+    }
+
+    async fn get_brightness(&mut self, image: &Path) -> Result<f32> {
+        if let Some(brightness) = self.brightness.get(image) {
+            return Ok(*brightness);
+        }
+        // the thumbnail is much faster to decode, and just as bright
+        let thumbnail = ensure_thumbnail(image).await?;
+        let brightness = spawn_blocking(move || mean_brightness(&thumbnail))
+            .await
+            .map_err(|e| anyhow!("Failed to join brightness task: {e:?}"))??;
+        self.brightness.insert(image.to_path_buf(), brightness);
+        Ok(brightness)
+    }
+
+    /// Get the image to show during a `slot`.
+    ///
+    /// Automatically chosen images are taken from the cache of the given `services`.
+    async fn get_slot_image(
+        &mut self,
+        slot: &TimeSlot,
+        services: &[ImageService],
+    ) -> Result<PathBuf> {
+        let brightest = match slot.image {
+            SlotImage::Path if slot.path.is_empty() => {
+                bail!("No image is selected for the {} wallpaper", slot.name)
+            }
+            SlotImage::Path => return Ok(PathBuf::from(&slot.path)),
+            SlotImage::Brightest => true,
+            SlotImage::Darkest => false,
+        };
+        let mut best: Option<(f32, PathBuf)> = None;
+        for service in services {
+            for image in list_cached_images(&self.app_cache_dir, service).await? {
+                let Ok(brightness) = check_err(self.get_brightness(&image).await) else {
+                    continue;
+                };
+                let is_better = best.as_ref().is_none_or(|(best, _)| {
+                    if brightest {
+                        brightness > *best
+                    } else {
+                        brightness < *best
+                    }
+                });
+                if is_better {
+                    best = Some((brightness, image));
+                }
+            }
+        }
+        best.map(|(_, image)| image).ok_or(anyhow!(
+            "No cached images to choose the {} wallpaper from",
+            slot.name
+        ))
+    }
+
+    /// Write a GNOME dynamic background for the `day_cycle` and get its path.
+    ///
+    /// The slots' images are copied next to it, so the background keeps working
+    /// if the cached images are pruned. Files of previous backgrounds are removed.
+    async fn write_gnome_background(&mut self, day_cycle: &DayCycle) -> Result<PathBuf> {
+        let dir = self.app_cache_dir.join(DAY_CYCLE_DIR);
+        if !dir.exists() {
+            fs::create_dir_all(&dir)
+                .await
+                .with_context(|| "Failed to create day cycle folder")?;
+        }
+        let mut images = vec![];
+        for (time, slot) in sorted_slots(&day_cycle.slots)? {
+            let image = self.get_slot_image(slot, &day_cycle.services).await?;
+            let name = image
+                .file_name()
+                .ok_or(anyhow!("Failed to get file name of {image:?}"))?
+                .to_string_lossy();
+            let dest = dir.join(format!("{:02}{:02}-{name}", slot.hour, slot.minute));
+            fs::copy(&image, &dest)
+                .await
+                .with_context(|| format!("Failed to copy {image:?} to day cycle folder"))?;
+            images.push((time, dest));
+        }
+        let xml = gnome_background_xml(&images, day_cycle.transition);
+        // a new name every time, so the desktop notices the change
+        let path = dir.join(format!(
+            "background-{}.xml",
+            Local::now().format("%Y%m%d%H%M%S")
+        ));
+        fs::write(&path, xml)
+            .await
+            .with_context(|| "Failed to write GNOME dynamic background")?;
+
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .with_context(|| "Failed to read day cycle folder contents.")?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .with_context(|| "Failed to traverse day cycle folder")?
+        {
+            let old = entry.path();
+            if old != path && images.iter().all(|(_, image)| *image != old) {
+                fs::remove_file(&old)
+                    .await
+                    .with_context(|| format!("Failed to remove old day cycle file {old:?}"))?;
+            }
+        }
+        Ok(path)
+    }
+
+    /// Get the wallpaper to set for the `day_cycle` at this time, and the name of what it shows.
+    async fn get_wallpaper(&mut self, day_cycle: &DayCycle) -> Result<(PathBuf, String)> {
+        match day_cycle.output {
+            DayCycleOutput::Switch => {
+                let slots = sorted_slots(&day_cycle.slots)?;
+                let slot = current_slot(&slots, Local::now().time())
+                    .map(|index| slots[index].1)
+                    .ok_or(anyhow!("No time slots are configured"))?
+                    .clone();
+                let image = self.get_slot_image(&slot, &day_cycle.services).await?;
+                Ok((image, format!("the {} wallpaper", slot.name)))
+            }
+            DayCycleOutput::GnomeXml => {
+                let background = self.write_gnome_background(day_cycle).await?;
+                Ok((background, "a dynamic background".to_string()))
+            }
+        }
+    }
+
+    async fn notify(&mut self, notification: NotificationAlert) {
+        if let Ok(result) = check_err(
+            self.notification_center
+                .send(NotificationUpdate(notification))
+                .await
+                .map_err(|e| anyhow!("Failed to send notification: {e:?}")),
+        ) {
+            let _ = check_err(result);
+        }
+    }
+}
+
+#[async_trait]
+impl Notifiable<SettingsChanged> for DayCycleActor {
+    async fn notify(&mut self, msg: SettingsChanged, _context: &MsgContext<Self>) {
+        if msg.old.day_cycle != msg.new.day_cycle {
+            self.restart_timer(&msg.new.day_cycle);
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<ApplyDayCycle> for DayCycleActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, _msg: ApplyDayCycle, _context: &MsgContext<Self>) -> Self::Result {
+        let settings = get_settings(&mut self.settings).await?;
+        debug_print!("Applying day cycle");
+        let mut day_cycle = settings.day_cycle.clone();
+        day_cycle
+            .services
+            .retain(|service| settings.is_service_enabled(*service));
+        let result = match self.get_wallpaper(&day_cycle).await {
+            Ok((path, name)) => self
+                .wallpaper
+                .send(SetWallpaper {
+                    selected: WallpaperSelection {
+                        path: path.to_string_lossy().to_string(),
+                        mode: Some(settings.mode),
+                        monitor: None,
+                    },
+                })
+                .await
+                .map_err(|e| anyhow!("{e:?}"))
+                .and_then(|result| result)
+                .map(|_| name),
+            Err(e) => Err(e),
+        };
+        let notification = match result {
+            Ok(name) => NotificationAlert {
+                id: NotificationAlert::next_id(),
+                title: "Day cycle".to_string(),
+                body: format!("Set the desktop wallpaper to {name}"),
+                percent: 1.0,
+                severity: NotificationSeverity::Info,
+                status: NotificationStatus::default(),
+            },
+            Err(e) => NotificationAlert {
+                id: NotificationAlert::next_id(),
+                title: "Day cycle".to_string(),
+                body: format!("Failed to set the desktop wallpaper: {e:?}"),
+                percent: 1.0,
+                severity: NotificationSeverity::Error,
+                status: NotificationStatus::default(),
+            },
+        };
+        self.notify(notification).await;
+        Ok(())
+    }
+}
+
+// Creates and spawns the actors in the async system.
+pub async fn create_actors(
+    app_cache_dir: PathBuf,
+    wallpaper: Address<WallpaperActor>,
+    notification_center: Address<NotificationActor>,
+    mut settings: Address<SettingsActor>,
+) -> Result<Address<DayCycleActor>> {
+    // Create actor contexts.
+    let day_cycle_context = MsgContext::new();
+    let day_cycle_addr = day_cycle_context.address();
+
+    // Spawn actors.
+    let day_cycle = get_settings(&mut settings).await?.day_cycle;
+    let mut actor = DayCycleActor::new(
+        day_cycle_addr.clone(),
+        app_cache_dir,
+        wallpaper,
+        notification_center,
+        settings,
+    );
+    actor.restart_timer(&day_cycle);
+    spawn(day_cycle_context.run(actor));
+    Ok(day_cycle_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{current_slot, gnome_background_xml, sorted_slots, DAY};
+    use crate::signals::{SlotImage, TimeSlot};
+    use chrono::NaiveTime;
+    use std::path::PathBuf;
+
+    fn slot(name: &str, hour: u8, minute: u8) -> TimeSlot {
+        TimeSlot {
+            name: name.to_string(),
+            hour,
+            minute,
+            image: SlotImage::Brightest,
+            path: String::new(),
+        }
+    }
+
+    #[test]
+    fn slots_are_sorted_and_invalid_times_rejected() {
+        let slots = [slot("night", 19, 0), slot("day", 7, 0), slot("dusk", 19, 0)];
+        let sorted = sorted_slots(&slots).unwrap();
+        let names: Vec<_> = sorted.iter().map(|(_, slot)| slot.name.as_str()).collect();
+        assert_eq!(names, ["day", "night"]);
+        let at = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+        assert_eq!(current_slot(&sorted, at(12)), Some(0));
+        // before the first slot starts, the last one of the previous day is still active
+        assert_eq!(current_slot(&sorted, at(5)), Some(1));
+
+        assert!(sorted_slots(&[slot("day", 7, 0), slot("night", 24, 0)]).is_err());
+    }
+
+    #[test]
+    fn gnome_background_fills_the_day() {
+        let images = [
+            (
+                NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+                PathBuf::from("/light & airy.jpg"),
+            ),
+            (
+                NaiveTime::from_hms_opt(19, 0, 0).unwrap(),
+                PathBuf::from("/dark.jpg"),
+            ),
+        ];
+        let xml = gnome_background_xml(&images, 1800);
+        let total: f64 = xml
+            .lines()
+            .filter_map(|line| {
+                line.trim()
+                    .strip_prefix("<duration>")?
+                    .strip_suffix("</duration>")?
+                    .parse::<f64>()
+                    .ok()
+            })
+            .sum();
+        assert_eq!(total, f64::from(DAY));
+        assert!(xml.contains("<hour>7</hour>"));
+        assert!(xml.contains("<duration>41400.0</duration>"));
+        assert!(xml.contains("<from>/dark.jpg</from>\n    <to>/light &amp; airy.jpg</to>"));
+    }
+}
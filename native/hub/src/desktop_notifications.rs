@@ -0,0 +1,180 @@
+//! Shows notifications on the desktop (outside of the app's window)
+//! through the freedesktop Notifications D-Bus interface.
+//!
+//! See <https://specifications.freedesktop.org/notification-spec/latest/>.
+#![cfg(all(
+    unix,
+    not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+))]
+
+use std::collections::HashMap;
+
+use crate::signals::{NotificationAlert, NotificationSeverity};
+use anyhow::{Context, Result};
+use zbus::{zvariant::Value, Connection};
+
+/// The name shown as the source of the notifications.
+const APP_NAME: &str = "Daily Wallpaper Images";
+
+#[zbus::proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+/// Get the notification spec's urgency level (low, normal or critical) for the `severity`.
+pub fn get_urgency(severity: NotificationSeverity) -> u8 {
+    match severity {
+        NotificationSeverity::Debug => 0,
+        NotificationSeverity::Info | NotificationSeverity::Warning => 1,
+        NotificationSeverity::Error => 2,
+    }
+}
+
+/// Keeps the connection to the session bus, which is only established when it is first needed.
+#[derive(Default)]
+pub struct DesktopNotifier {
+    connection: Option<Connection>,
+}
+
+impl DesktopNotifier {
+    /// Use the given `connection` instead of the session bus (e.g. a private bus in tests).
+    pub fn with_connection(connection: Connection) -> Self {
+        Self {
+            connection: Some(connection),
+        }
+    }
+
+    pub async fn get_connection(&mut self) -> Result<Connection> {
+        if let Some(connection) = &self.connection {
+            return Ok(connection.clone());
+        }
+        let connection = Connection::session()
+            .await
+            .with_context(|| "Failed to connect to the D-Bus session bus")?;
+        self.connection = Some(connection.clone());
+        Ok(connection)
+    }
+}
+
+/// Show the `alert` on the desktop.
+///
+/// Returns the ID that the notification server assigned to the notification.
+pub async fn show_notification(connection: &Connection, alert: &NotificationAlert) -> Result<u32> {
+    let proxy = NotificationsProxy::new(connection)
+        .await
+        .with_context(|| "Failed to reach the desktop's notification server")?;
+    let hints = HashMap::from([("urgency", Value::U8(get_urgency(alert.severity)))]);
+    proxy
+        // -1 lets the server decide when the notification expires
+        .notify(APP_NAME, 0, "", &alert.title, &alert.body, &[], hints, -1)
+        .await
+        .with_context(|| "Failed to show desktop notification")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{show_notification, DesktopNotifier};
+    use crate::signals::{NotificationAlert, NotificationSeverity, NotificationStatus};
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+        sync::{Arc, Mutex},
+    };
+    use zbus::{connection::Builder, zvariant::OwnedValue};
+
+    /// A notification server that records the notifications it received.
+    struct FakeServer {
+        received: Arc<Mutex<Vec<(String, u8)>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl FakeServer {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: String,
+            _replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            _body: String,
+            _actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let urgency = hints
+                .get("urgency")
+                .and_then(|value| u8::try_from(value).ok())
+                .unwrap_or_default();
+            let mut received = self.received.lock().unwrap();
+            received.push((summary, urgency));
+            received.len() as u32
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_to_private_bus() {
+        let Ok(mut bus) = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+        else {
+            // dbus-daemon is not installed
+            return;
+        };
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim();
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let _server = Builder::address(address)
+            .unwrap()
+            .name("org.freedesktop.Notifications")
+            .unwrap()
+            .serve_at(
+                "/org/freedesktop/Notifications",
+                FakeServer {
+                    received: received.clone(),
+                },
+            )
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let client = Builder::address(address).unwrap().build().await.unwrap();
+        let mut notifier = DesktopNotifier::with_connection(client);
+        let connection = notifier.get_connection().await.unwrap();
+        let alert = NotificationAlert {
+            id: NotificationAlert::next_id(),
+            title: "Bing images".to_string(),
+            body: "Failed to get list of images".to_string(),
+            percent: 1.0,
+            severity: NotificationSeverity::Error,
+            status: NotificationStatus::default(),
+        };
+        let id = show_notification(&connection, &alert).await;
+        bus.kill().unwrap();
+        assert_eq!(id.unwrap(), 1);
+        assert_eq!(
+            received.lock().unwrap().as_slice(),
+            [("Bing images".to_string(), 2)]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Entry points for frontends that run without the Flutter UI,
+//! like the `daily-wallpaper` command-line binary.
+//!
+//! These create the same actors that the app uses,
+//! but progress is reported through a channel instead of Dart signals.
+
+use std::path::PathBuf;
+
+#[cfg(all(unix, not(target_os = "android")))]
+use crate::control;
+use crate::{
+    notification_center::{NotificationActor, Notifications},
+    services::{self, ImageServiceActor},
+    settings_center::{self, SettingsActor},
+    signals::NotificationAlert,
+};
+#[cfg(not(target_os = "android"))]
+use anyhow::anyhow;
+use anyhow::Result;
+use messages::prelude::{Address, Context as MsgContext};
+use tokio::{spawn, sync::mpsc::UnboundedSender};
+
+#[cfg(not(target_os = "android"))]
+use crate::{
+    archive::{self, ArchiveActor},
+    day_cycle::{self, DayCycleActor},
+    scheduler::{self, SchedulerActor},
+    set_wallpaper::{self, WallpaperActor},
+    settings_center::SettingsDependents,
+    slideshow::{self, SlideshowActor},
+};
+
+/// The addresses of the actors created by [`create_actors()`].
+#[derive(Clone)]
+pub struct Hub {
+    /// The folder where all cached data is stored.
+    pub app_cache_dir: PathBuf,
+    pub notification_center: Address<NotificationActor>,
+    /// The owner of the settings, which all reads and changes go through.
+    pub settings: Address<SettingsActor>,
+    pub image_service: Address<ImageServiceActor>,
+    #[cfg(not(target_os = "android"))]
+    pub wallpaper: Address<WallpaperActor>,
+    #[cfg(not(target_os = "android"))]
+    pub archive: Address<ArchiveActor>,
+}
+
+/// Creates and spawns the actors in the async system.
+///
+/// Every notification update is forwarded to the `listener`.
+pub async fn create_actors(listener: UnboundedSender<NotificationAlert>) -> Result<Hub> {
+    let notification_context = MsgContext::new();
+    let notification_center = notification_context.address();
+    let app_cache_dir = services::get_app_cache_dir()?;
+    // changes of the settings are saved by a running daemon (if any),
+    // so that it applies them right away
+    #[cfg(all(unix, not(target_os = "android")))]
+    let daemon = control::find_daemon().await;
+    #[cfg(not(all(unix, not(target_os = "android"))))]
+    let daemon = None;
+    let mut settings =
+        settings_center::create_actors(app_cache_dir.clone(), notification_center.clone(), daemon)
+            .await?;
+    let current = settings_center::get_settings(&mut settings).await?;
+    let notifications = Notifications::load(&app_cache_dir).await;
+    let actor = NotificationActor::with_listener(
+        notification_center.clone(),
+        app_cache_dir.clone(),
+        notifications,
+        &current,
+        listener,
+    );
+    spawn(notification_context.run(actor));
+
+    let image_service =
+        services::create_actors(notification_center.clone(), settings.clone(), None).await?;
+    #[cfg(not(target_os = "android"))]
+    let wallpaper =
+        set_wallpaper::create_actors(notification_center.clone(), settings.clone(), None).await?;
+    #[cfg(not(target_os = "android"))]
+    let archive = archive::create_actors(
+        app_cache_dir.clone(),
+        wallpaper.clone(),
+        notification_center.clone(),
+        settings.clone(),
+        None,
+    )
+    .await?;
+    Ok(Hub {
+        app_cache_dir,
+        notification_center,
+        settings,
+        image_service,
+        #[cfg(not(target_os = "android"))]
+        wallpaper,
+        #[cfg(not(target_os = "android"))]
+        archive,
+    })
+}
+
+/// The addresses of the actors created by [`create_daemon_actors()`].
+#[cfg(not(target_os = "android"))]
+#[derive(Clone)]
+pub struct Daemon {
+    pub hub: Hub,
+    pub scheduler: Address<SchedulerActor>,
+    pub slideshow: Address<SlideshowActor>,
+    pub day_cycle: Address<DayCycleActor>,
+}
+
+/// Like [`create_actors()`], but this also creates the actors that
+/// change the wallpaper automatically (the scheduler, the slideshow and the day cycle).
+///
+/// The settings file is watched, so that changes to it are applied while the daemon runs.
+///
+/// This is meant for long-running processes.
+#[cfg(not(target_os = "android"))]
+pub async fn create_daemon_actors(listener: UnboundedSender<NotificationAlert>) -> Result<Daemon> {
+    let mut hub = create_actors(listener).await?;
+    set_wallpaper::restore_monitor_wallpapers(hub.wallpaper.clone());
+    let scheduler = scheduler::create_actors(
+        hub.app_cache_dir.clone(),
+        hub.image_service.clone(),
+        hub.wallpaper.clone(),
+        hub.notification_center.clone(),
+        hub.settings.clone(),
+    )
+    .await?;
+    let slideshow = slideshow::create_actors(
+        hub.app_cache_dir.clone(),
+        hub.wallpaper.clone(),
+        hub.notification_center.clone(),
+        hub.settings.clone(),
+    )
+    .await?;
+    let day_cycle = day_cycle::create_actors(
+        hub.app_cache_dir.clone(),
+        hub.wallpaper.clone(),
+        hub.notification_center.clone(),
+        hub.settings.clone(),
+    )
+    .await?;
+    hub.settings
+        .send(SettingsDependents {
+            scheduler: Some(scheduler.clone()),
+            slideshow: Some(slideshow.clone()),
+            day_cycle: Some(day_cycle.clone()),
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to register settings dependents: {e:?}"))?;
+    Ok(Daemon {
+        hub,
+        scheduler,
+        slideshow,
+        day_cycle,
+    })
+}
@@ -1,13 +1,38 @@
 //! This `hub` crate is the
 //! entry point of the Rust logic.
 
+#[cfg(not(target_os = "android"))]
+pub mod archive;
+#[cfg(not(target_os = "android"))]
+pub mod backends;
+#[cfg(not(target_os = "android"))]
+pub mod caption;
 pub(crate) mod common;
-pub(crate) mod notification_center;
+#[cfg(all(unix, not(target_os = "android")))]
+pub mod control;
+#[cfg(not(target_os = "android"))]
+pub mod day_cycle;
+#[cfg(all(
+    unix,
+    not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+))]
+pub mod desktop_notifications;
+pub mod headless;
 #[cfg(not(target_os = "android"))]
-pub(crate) mod set_wallpaper;
-pub(crate) mod signals;
+pub mod monitors;
+pub mod notification_center;
+#[cfg(not(target_os = "android"))]
+pub mod processing;
+#[cfg(not(target_os = "android"))]
+pub mod scheduler;
+#[cfg(not(target_os = "android"))]
+pub mod set_wallpaper;
+pub mod signals;
 use rinf::{dart_shutdown, write_interface};
 pub mod services;
+pub mod settings_center;
+#[cfg(not(target_os = "android"))]
+pub mod slideshow;
 
 // Uncomment below to target the web.
 // use tokio_with_wasm::alias as tokio;
@@ -0,0 +1,195 @@
+//! Enumerate monitors and set a different wallpaper on each of them.
+//!
+//! The `wallpaper` crate only sets one image for all screens,
+//! so this talks to the desktop directly where the platform allows it:
+//!
+//! | Desktop | List monitors | Set per monitor |
+//! |---------|:-------------:|:---------------:|
+//! | sway    | yes           | yes             |
+//! | X11     | yes           | no              |
+//!
+//! Other platforms report an error.
+#![cfg(not(target_os = "android"))]
+
+use crate::signals::{Monitor, WallpaperMode};
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::process::Command;
+
+/// Run a `program` with the given `args` and get its standard output.
+async fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "`{program} {}` failed ({}): {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn is_sway() -> bool {
+    cfg!(target_os = "linux") && std::env::var_os("SWAYSOCK").is_some()
+}
+
+fn is_x11() -> bool {
+    cfg!(target_os = "linux")
+        && std::env::var_os("DISPLAY").is_some()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+}
+
+mod sway {
+    use super::{run, Monitor, WallpaperMode};
+    use anyhow::{Context, Result};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct OutputMode {
+        width: u32,
+        height: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Output {
+        name: String,
+        active: bool,
+        #[serde(default)]
+        primary: bool,
+        current_mode: Option<OutputMode>,
+    }
+
+    pub async fn list_monitors() -> Result<Vec<Monitor>> {
+        let text = run("swaymsg", &["--raw", "--type", "get_outputs"]).await?;
+        let outputs: Vec<Output> =
+            serde_json::from_str(&text).with_context(|| "Failed to parse sway outputs")?;
+        Ok(outputs
+            .into_iter()
+            .filter(|output| output.active)
+            .map(|output| {
+                let (width, height) = output
+                    .current_mode
+                    .map(|mode| (mode.width, mode.height))
+                    .unwrap_or_default();
+                Monitor {
+                    name: output.name,
+                    width,
+                    height,
+                    primary: output.primary,
+                    wallpaper: None,
+                }
+            })
+            .collect())
+    }
+
+    pub async fn set_wallpaper(
+        monitor: &str,
+        path: &str,
+        mode: Option<WallpaperMode>,
+    ) -> Result<()> {
+        let mode = match mode {
+            Some(WallpaperMode::Center) => "center",
+            Some(WallpaperMode::Fit) => "fit",
+            Some(WallpaperMode::Stretch) => "stretch",
+            Some(WallpaperMode::Tile) => "tile",
+            // `Auto` is resolved before a wallpaper is set
+            Some(WallpaperMode::Crop | WallpaperMode::Auto) | None => "fill",
+        };
+        run("swaymsg", &["output", monitor, "bg", path, mode]).await?;
+        Ok(())
+    }
+}
+
+mod xrandr {
+    use super::{run, Monitor};
+    use anyhow::Result;
+
+    /// Parse a line of `xrandr --listmonitors`, like
+    /// ` 0: +*DP-1 2560/597x1440/336+0+0  DP-1`.
+    pub(super) fn parse_monitor(line: &str) -> Option<Monitor> {
+        let mut fields = line.split_whitespace();
+        fields.next()?.strip_suffix(':')?;
+        let flags = fields.next()?;
+        let (width, rest) = fields.next()?.split_once('/')?;
+        let (_, height) = rest.split_once('x')?;
+        let (height, _) = height.split_once('/')?;
+        Some(Monitor {
+            name: fields.next()?.to_string(),
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+            primary: flags.contains('*'),
+            wallpaper: None,
+        })
+    }
+
+    pub async fn list_monitors() -> Result<Vec<Monitor>> {
+        let text = run("xrandr", &["--listmonitors"]).await?;
+        Ok(text.lines().skip(1).filter_map(parse_monitor).collect())
+    }
+}
+
+/// List the monitors connected to the desktop.
+pub async fn list_monitors() -> Result<Vec<Monitor>> {
+    if is_sway() {
+        sway::list_monitors().await
+    } else if is_x11() {
+        xrandr::list_monitors().await
+    } else {
+        Err(anyhow!("Listing monitors is not supported on this desktop"))
+    }
+}
+
+/// Set the wallpaper of a single `monitor` (by name).
+pub async fn set_monitor_wallpaper(
+    monitor: &str,
+    path: &str,
+    mode: Option<WallpaperMode>,
+) -> Result<()> {
+    if is_sway() {
+        sway::set_wallpaper(monitor, path, mode).await
+    } else {
+        Err(anyhow!(
+            "Setting a different wallpaper per monitor is not supported on this desktop"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::xrandr::parse_monitor;
+
+    #[test]
+    fn parses_xrandr_monitors() {
+        let primary = parse_monitor(" 0: +*DP-1 2560/597x1440/336+0+0  DP-1").unwrap();
+        assert_eq!(primary.name, "DP-1");
+        assert_eq!((primary.width, primary.height), (2560, 1440));
+        assert!(primary.primary);
+
+        let other = parse_monitor(" 1: +HDMI-1 1920/531x1080/299+2560+0  HDMI-1").unwrap();
+        assert_eq!(other.name, "HDMI-1");
+        assert!(!other.primary);
+
+        // a rotated monitor reports its rotated size
+        let rotated = parse_monitor(" 2: +eDP-1 1200/286x1920/179+4480+0  eDP-1").unwrap();
+        assert_eq!((rotated.width, rotated.height), (1200, 1920));
+    }
+
+    #[test]
+    fn skips_other_xrandr_lines() {
+        assert!(parse_monitor("Monitors: 2").is_none());
+        // lines of `xrandr --query` (e.g. for a disconnected output)
+        assert!(
+            parse_monitor("HDMI-2 disconnected (normal left inverted right x axis y axis)")
+                .is_none()
+        );
+        assert!(parse_monitor(
+            "DP-1 connected primary 2560x1440+0+0 (normal left inverted right x axis y axis) 597mm x 336mm"
+        )
+        .is_none());
+        assert!(parse_monitor("").is_none());
+    }
+}
@@ -1,19 +1,133 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
+#[cfg(all(unix, not(target_os = "android")))]
+use crate::control;
+#[cfg(all(
+    unix,
+    not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+))]
+use crate::desktop_notifications::{show_notification, DesktopNotifier};
 #[cfg(not(target_os = "android"))]
-use crate::set_wallpaper;
-use crate::{services, signals::notifications::*};
-use anyhow::Result;
-use chrono::{SecondsFormat, Utc};
-use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler};
+use crate::{
+    archive, day_cycle, scheduler, set_wallpaper,
+    settings_center::{SettingsActor, SettingsDependents},
+    slideshow,
+};
+use crate::{
+    common::check_err,
+    services::{
+        self,
+        settings::{Settings, SettingsChanged},
+    },
+    settings_center,
+    signals::notifications::*,
+};
+#[cfg(not(target_os = "android"))]
+use anyhow::anyhow;
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler, Notifiable};
 use rinf::{debug_print, DartSignal, RustSignal};
-use tokio::spawn;
+use tokio::{
+    fs, spawn,
+    sync::mpsc::UnboundedSender,
+    time::{sleep_until, Duration, Instant},
+};
+
+/// The name of the file (in the app's cache folder) that keeps the notifications across restarts.
+const NOTIFICATIONS_FILE: &str = "notifications.json";
+
+/// The number of finished notifications that are kept.
+const MAX_NOTIFICATIONS: usize = 50;
+
+/// How long finished notifications are kept.
+const MAX_NOTIFICATION_AGE: TimeDelta = TimeDelta::days(7);
 
 pub struct NotificationUpdate(pub NotificationAlert);
 
+/// Tells the [`NotificationActor`] to send the notifications that were held back
+/// (to limit the rate of updates).
+struct FlushNotifications;
+
+/// Get the shortest time between two updates sent to Dart,
+/// given the maximum number of updates per second (or 0 for no limit).
+fn get_update_interval(rate: u32) -> Duration {
+    match rate {
+        0 => Duration::ZERO,
+        rate => Duration::from_secs(1) / rate,
+    }
+}
+
 pub struct Notifications(pub HashMap<String, NotificationAlert>);
 
 impl Notifications {
+    /// Read the notifications saved in `app_cache_dir`.
+    ///
+    /// Notifications of operations that were still in progress are dropped,
+    /// because those operations were interrupted.
+    pub async fn load(app_cache_dir: &Path) -> Self {
+        let path = app_cache_dir.join(NOTIFICATIONS_FILE);
+        if !path.exists() {
+            return Self(HashMap::new());
+        }
+        let mut notifications = check_err(
+            fs::read_to_string(path)
+                .await
+                .with_context(|| "Failed to read saved notifications")
+                .and_then(|text| {
+                    serde_json::from_str::<HashMap<String, NotificationAlert>>(&text)
+                        .with_context(|| "Failed to deserialize saved notifications")
+                }),
+        )
+        .unwrap_or_default();
+        notifications.retain(|_, alert| alert.percent >= 1.0);
+        Self(notifications)
+    }
+
+    /// Write the finished notifications to `app_cache_dir`.
+    async fn save(&self, app_cache_dir: &Path) -> Result<()> {
+        let finished = self
+            .0
+            .iter()
+            .filter(|(_, alert)| alert.percent >= 1.0)
+            .collect::<HashMap<_, _>>();
+        fs::write(
+            app_cache_dir.join(NOTIFICATIONS_FILE),
+            serde_json::to_string(&finished)
+                .with_context(|| "Failed to convert notifications to JSON")?,
+        )
+        .await
+        .with_context(|| "Failed to write notifications")
+    }
+
+    /// Remove finished notifications that are older than [`MAX_NOTIFICATION_AGE`],
+    /// and then the oldest ones beyond [`MAX_NOTIFICATIONS`].
+    ///
+    /// The keys are the times that the notifications were added (formatted as RFC 3339).
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.0.retain(|key, alert| {
+            alert.percent < 1.0
+                || DateTime::parse_from_rfc3339(key)
+                    .is_ok_and(|added| now.signed_duration_since(added) <= MAX_NOTIFICATION_AGE)
+        });
+        let mut finished = self
+            .0
+            .iter()
+            .filter(|(_, alert)| alert.percent >= 1.0)
+            .map(|(key, _)| key.to_owned())
+            .collect::<Vec<_>>();
+        if finished.len() > MAX_NOTIFICATIONS {
+            // all keys are in UTC, so they sort chronologically
+            finished.sort_unstable();
+            for key in &finished[..finished.len() - MAX_NOTIFICATIONS] {
+                self.0.remove(key);
+            }
+        }
+    }
+
     fn get_pending(&self) -> Vec<String> {
         self.0
             .iter()
@@ -29,19 +143,165 @@ impl Notifications {
 }
 pub struct NotificationActor {
     notifications: Notifications,
+    listener: Option<UnboundedSender<NotificationAlert>>,
+    /// Where the notifications are saved.
+    app_cache_dir: PathBuf,
+    /// Whether a running daemon saves the notifications instead.
+    read_only: bool,
+    #[cfg(all(
+        unix,
+        not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+    ))]
+    desktop: DesktopNotifier,
+    /// Which notifications are also shown on the desktop.
+    #[cfg(all(
+        unix,
+        not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+    ))]
+    desktop_options: DesktopNotifications,
+    notification_addr: Address<Self>,
+    /// The shortest time between two updates sent to Dart.
+    update_interval: Duration,
+    /// When the notifications were last sent to Dart.
+    last_sent: Option<Instant>,
+    /// Whether there are changes that were not sent to Dart yet.
+    held_back: bool,
+    /// Whether a [`FlushNotifications`] message is on its way.
+    flush_scheduled: bool,
 }
 
 impl Actor for NotificationActor {}
 impl NotificationActor {
-    pub fn new(notification_addr: Address<Self>) -> Self {
+    /// Create the actor with the `notifications` saved earlier.
+    ///
+    /// The rate of updates sent to Dart and the desktop notifications are configured
+    /// by the current `settings`.
+    /// If `read_only`, then a running daemon saves the notifications instead.
+    pub fn new(
+        notification_addr: Address<Self>,
+        app_cache_dir: PathBuf,
+        notifications: Notifications,
+        settings: &Settings,
+        read_only: bool,
+    ) -> Self {
         spawn(Self::listen_to_refresh(notification_addr.clone()));
         spawn(Self::listen_to_dismiss(notification_addr.clone()));
-        spawn(Self::listen_to_dismiss_all(notification_addr));
+        spawn(Self::listen_to_dismiss_all(notification_addr.clone()));
+        Self {
+            notifications,
+            listener: None,
+            app_cache_dir,
+            read_only,
+            notification_addr,
+            update_interval: get_update_interval(settings.notification_rate),
+            last_sent: None,
+            held_back: false,
+            flush_scheduled: false,
+            #[cfg(all(
+                unix,
+                not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+            ))]
+            desktop: DesktopNotifier::default(),
+            #[cfg(all(
+                unix,
+                not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+            ))]
+            desktop_options: settings.desktop_notifications.clone(),
+        }
+    }
+
+    /// Like [`Self::new`], but every notification update is also forwarded to the `listener`.
+    ///
+    /// This lets frontends without a Dart UI report the progress of operations.
+    pub fn with_listener(
+        notification_addr: Address<Self>,
+        app_cache_dir: PathBuf,
+        notifications: Notifications,
+        settings: &Settings,
+        listener: UnboundedSender<NotificationAlert>,
+    ) -> Self {
         Self {
-            notifications: Notifications(HashMap::new()),
+            listener: Some(listener),
+            ..Self::new(
+                notification_addr,
+                app_cache_dir,
+                notifications,
+                settings,
+                false,
+            )
         }
     }
 
+    /// Send all notifications to Dart now.
+    fn send_results(&mut self, just_finished: Vec<String>) {
+        self.held_back = false;
+        self.last_sent = Some(Instant::now());
+        NotificationResults {
+            notifications: self.notifications.0.clone(),
+            pending: self.notifications.get_pending(),
+            just_finished,
+        }
+        .send_signal_to_dart();
+    }
+
+    /// Send all notifications to Dart, unless they were sent too recently.
+    ///
+    /// Otherwise, the latest state is sent once the update interval passed,
+    /// so that any updates in between are combined into one.
+    fn send_results_throttled(&mut self) {
+        let next = self.last_sent.map(|sent| sent + self.update_interval);
+        match next {
+            Some(next) if next > Instant::now() => {
+                self.held_back = true;
+                if !self.flush_scheduled {
+                    self.flush_scheduled = true;
+                    let mut notification_addr = self.notification_addr.clone();
+                    spawn(async move {
+                        sleep_until(next).await;
+                        let _ = notification_addr.send(FlushNotifications).await;
+                    });
+                }
+            }
+            _ => self.send_results(Vec::new()),
+        }
+    }
+
+    /// Show the just finished notifications (with the given `keys`) on the desktop,
+    /// if the settings allow it.
+    #[cfg(all(
+        unix,
+        not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+    ))]
+    async fn forward_to_desktop(&mut self, keys: &[String]) {
+        let alerts = keys
+            .iter()
+            .filter_map(|key| self.notifications.0.get(key))
+            .filter(|alert| self.desktop_options.includes(alert.severity))
+            .cloned()
+            .collect::<Vec<_>>();
+        if alerts.is_empty() {
+            return;
+        }
+        let Ok(connection) = check_err(self.desktop.get_connection().await) else {
+            return;
+        };
+        // a slow notification server should not hold up the notification center
+        spawn(async move {
+            for alert in alerts {
+                let _ = check_err(show_notification(&connection, &alert).await);
+            }
+        });
+    }
+
+    /// Save the finished notifications, so they are still shown after a restart.
+    async fn persist(&mut self) {
+        self.notifications.prune(Utc::now());
+        if self.read_only {
+            return;
+        }
+        let _ = check_err(self.notifications.save(&self.app_cache_dir).await);
+    }
+
     async fn listen_to_refresh(mut notification_addr: Address<Self>) {
         let receiver = NotificationRefresh::get_dart_signal_receiver();
         // Continuously listen for signals.
@@ -82,46 +342,85 @@ impl Handler<NotificationUpdate> for NotificationActor {
         msg: NotificationUpdate,
         _context: &MsgContext<Self>,
     ) -> Self::Result {
-        let mut found = false;
-        let mut old_key = None;
+        if let Some(listener) = &self.listener {
+            // the listener may have hung up; it does not affect the notifications kept here
+            let _ = listener.send(msg.0.clone());
+        }
         let mut just_finished = Vec::with_capacity(1);
-        for (key, val) in self.notifications.0.iter_mut() {
-            if val.title == msg.0.title {
-                if val.percent >= 1.0 {
-                    old_key = Some(key.to_owned());
-                    break;
-                }
-                if msg.0.percent >= 1.0 {
-                    just_finished.push(key.to_owned());
-                }
-                val.update(msg.0.clone());
-                found = true;
-                // debug_print!("Updated notification \"{}\" ({})", val.title, val.percent);
-                break;
+        let existing = self
+            .notifications
+            .0
+            .iter_mut()
+            .find(|(_, val)| val.id == msg.0.id);
+        if let Some((key, val)) = existing {
+            if val.percent < 1.0 && msg.0.percent >= 1.0 {
+                just_finished.push(key.to_owned());
             }
-        }
-        if let Some(old_key) = old_key {
-            let old = self.notifications.0.remove(&old_key).unwrap();
-            debug_print!("Removed outdated notification \"{}\"", old.title);
-        }
-        if !found {
+            val.update(msg.0);
+            // debug_print!("Updated notification \"{}\" ({})", val.title, val.percent);
+        } else {
             debug_print!("Adding new notification \"{}\"", msg.0.title);
-            let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+            // the key is only used for ordering, but it must be unique
+            let mut added = Utc::now();
+            let mut timestamp = added.to_rfc3339_opts(SecondsFormat::Millis, false);
+            while self.notifications.0.contains_key(&timestamp) {
+                added += TimeDelta::milliseconds(1);
+                timestamp = added.to_rfc3339_opts(SecondsFormat::Millis, false);
+            }
             if msg.0.percent >= 1.0 {
                 just_finished.push(timestamp.clone());
             }
             self.notifications.0.insert(timestamp, msg.0);
         }
-        NotificationResults {
-            notifications: self.notifications.0.clone(),
-            pending: self.notifications.get_pending(),
-            just_finished,
+        // progress updates are frequent, so only finished notifications are saved
+        if !just_finished.is_empty() {
+            self.persist().await;
+            #[cfg(all(
+                unix,
+                not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+            ))]
+            self.forward_to_desktop(&just_finished).await;
+        }
+        if just_finished.is_empty() {
+            self.send_results_throttled();
+        } else {
+            // the final state of an operation is never held back
+            self.send_results(just_finished);
         }
-        .send_signal_to_dart();
         Ok(())
     }
 }
 
+#[async_trait]
+impl Handler<FlushNotifications> for NotificationActor {
+    type Result = ();
+
+    async fn handle(
+        &mut self,
+        _msg: FlushNotifications,
+        _context: &MsgContext<Self>,
+    ) -> Self::Result {
+        self.flush_scheduled = false;
+        if self.held_back {
+            self.send_results(Vec::new());
+        }
+    }
+}
+
+#[async_trait]
+impl Notifiable<SettingsChanged> for NotificationActor {
+    async fn notify(&mut self, msg: SettingsChanged, _context: &MsgContext<Self>) {
+        self.update_interval = get_update_interval(msg.new.notification_rate);
+        #[cfg(all(
+            unix,
+            not(any(target_os = "android", target_os = "ios", target_os = "macos"))
+        ))]
+        {
+            self.desktop_options = msg.new.desktop_notifications;
+        }
+    }
+}
+
 #[async_trait]
 impl Handler<NotificationRefresh> for NotificationActor {
     type Result = Result<()>;
@@ -131,12 +430,7 @@ impl Handler<NotificationRefresh> for NotificationActor {
         _msg: NotificationRefresh,
         _context: &MsgContext<Self>,
     ) -> Self::Result {
-        NotificationResults {
-            notifications: self.notifications.0.clone(),
-            pending: self.notifications.get_pending(),
-            ..Default::default()
-        }
-        .send_signal_to_dart();
+        self.send_results(Vec::new());
         debug_print!("Done refreshing notifications");
         Ok(())
     }
@@ -151,14 +445,16 @@ impl Handler<NotificationDismiss> for NotificationActor {
         msg: NotificationDismiss,
         _context: &MsgContext<Self>,
     ) -> Self::Result {
-        if let Some(entry) = self.notifications.0.remove_entry(&msg.timestamp) {
+        let key = self
+            .notifications
+            .0
+            .iter()
+            .find(|(_, alert)| alert.id == msg.id)
+            .map(|(key, _)| key.clone());
+        if let Some(entry) = key.and_then(|key| self.notifications.0.remove_entry(&key)) {
             debug_print!("Dismissed \"{}\"", entry.1.title);
-            NotificationResults {
-                notifications: self.notifications.0.clone(),
-                pending: self.notifications.get_pending(),
-                ..Default::default()
-            }
-            .send_signal_to_dart();
+            self.persist().await;
+            self.send_results(Vec::new());
         }
         Ok(())
     }
@@ -175,12 +471,8 @@ impl Handler<NotificationDismissAll> for NotificationActor {
     ) -> Self::Result {
         self.notifications.0.clear();
         debug_print!("Dismissed all notifications");
-        NotificationResults {
-            notifications: self.notifications.0.clone(),
-            pending: Vec::new(),
-            ..Default::default()
-        }
-        .send_signal_to_dart();
+        self.persist().await;
+        self.send_results(Vec::new());
         Ok(())
     }
 }
@@ -192,14 +484,152 @@ pub async fn create_actors() -> Result<()> {
     let notification_addr = notification_context.address();
 
     // Spawn actors.
-    let actor = NotificationActor::new(notification_addr.clone());
+    let app_cache_dir = services::get_app_cache_dir()?;
+    // a running daemon changes the cached files instead of the app's actors
+    #[cfg(all(unix, not(target_os = "android")))]
+    let daemon = control::find_daemon().await;
+    #[cfg(not(all(unix, not(target_os = "android"))))]
+    let daemon = None;
+    // the other actors read (and change) the settings through the settings center
+    let mut settings_addr = settings_center::create_actors(
+        app_cache_dir.clone(),
+        notification_addr.clone(),
+        daemon.clone(),
+    )
+    .await?;
+    let settings = settings_center::get_settings(&mut settings_addr).await?;
+    let notifications = Notifications::load(&app_cache_dir).await;
+    let actor = NotificationActor::new(
+        notification_addr.clone(),
+        app_cache_dir.clone(),
+        notifications,
+        &settings,
+        daemon.is_some(),
+    );
     spawn(notification_context.run(actor));
 
-    // now spawn sub tasks that can send messages to this task
-    spawn(services::create_actors(notification_addr.clone()));
+    // now create actors that can send messages to this actor
+    #[cfg_attr(target_os = "android", allow(unused_variables))]
+    let service_addr = services::create_actors(
+        notification_addr.clone(),
+        settings_addr.clone(),
+        daemon.clone(),
+    )
+    .await?;
     #[cfg(not(target_os = "android"))]
     {
-        spawn(set_wallpaper::create_actors(notification_addr.clone()));
+        let wallpaper_addr = set_wallpaper::create_actors(
+            notification_addr.clone(),
+            settings_addr.clone(),
+            daemon.clone(),
+        )
+        .await?;
+        archive::create_actors(
+            app_cache_dir.clone(),
+            wallpaper_addr.clone(),
+            notification_addr.clone(),
+            settings_addr.clone(),
+            daemon.clone(),
+        )
+        .await?;
+        let dependents = create_automatic_actors(
+            app_cache_dir,
+            daemon,
+            service_addr,
+            wallpaper_addr,
+            notification_addr,
+            settings_addr.clone(),
+        )
+        .await?;
+        settings_addr
+            .send(dependents)
+            .await
+            .map_err(|e| anyhow!("Failed to register settings dependents: {e:?}"))?;
     }
     Ok(())
 }
+
+/// Create the actors that change the wallpaper automatically,
+/// unless a running daemon already does that.
+#[cfg(not(target_os = "android"))]
+async fn create_automatic_actors(
+    app_cache_dir: PathBuf,
+    #[cfg_attr(not(unix), allow(unused_variables))] daemon: Option<PathBuf>,
+    service_addr: Address<services::ImageServiceActor>,
+    wallpaper_addr: Address<set_wallpaper::WallpaperActor>,
+    notification_addr: Address<NotificationActor>,
+    settings_addr: Address<SettingsActor>,
+) -> Result<SettingsDependents> {
+    // let the daemon (if running) handle automatic wallpaper changes
+    #[cfg(unix)]
+    if let Some(socket_path) = daemon {
+        debug_print!("Attaching to the daemon listening on {socket_path:?}");
+        control::attach(socket_path, notification_addr);
+        return Ok(SettingsDependents::default());
+    }
+    set_wallpaper::restore_monitor_wallpapers(wallpaper_addr.clone());
+    let scheduler = scheduler::create_actors(
+        app_cache_dir.clone(),
+        service_addr,
+        wallpaper_addr.clone(),
+        notification_addr.clone(),
+        settings_addr.clone(),
+    )
+    .await?;
+    let slideshow = slideshow::create_actors(
+        app_cache_dir.clone(),
+        wallpaper_addr.clone(),
+        notification_addr.clone(),
+        settings_addr.clone(),
+    )
+    .await?;
+    let day_cycle = day_cycle::create_actors(
+        app_cache_dir,
+        wallpaper_addr,
+        notification_addr,
+        settings_addr,
+    )
+    .await?;
+    Ok(SettingsDependents {
+        scheduler: Some(scheduler),
+        slideshow: Some(slideshow),
+        day_cycle: Some(day_cycle),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Notifications, MAX_NOTIFICATIONS};
+    use crate::signals::{NotificationAlert, NotificationSeverity, NotificationStatus};
+    use chrono::{SecondsFormat, TimeDelta, TimeZone, Utc};
+    use std::collections::HashMap;
+
+    #[test]
+    fn prune_keeps_recent_and_pending() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 31, 12, 0, 0).unwrap();
+        let alert = |percent| NotificationAlert {
+            id: NotificationAlert::next_id(),
+            title: "Bing images".to_string(),
+            body: String::new(),
+            percent,
+            severity: NotificationSeverity::Info,
+            status: NotificationStatus::default(),
+        };
+        let key = |minutes| {
+            (now - TimeDelta::minutes(minutes)).to_rfc3339_opts(SecondsFormat::Millis, false)
+        };
+        let mut notifications = Notifications(HashMap::new());
+        for minutes in 0..(MAX_NOTIFICATIONS as i64 + 5) {
+            notifications.0.insert(key(minutes), alert(1.0));
+        }
+        // too old, unless still in progress
+        notifications.0.insert(key(8 * 24 * 60), alert(1.0));
+        notifications.0.insert(key(9 * 24 * 60), alert(0.5));
+
+        notifications.prune(now);
+        assert_eq!(notifications.0.len(), MAX_NOTIFICATIONS + 1);
+        assert!(notifications.0.contains_key(&key(0)));
+        assert!(!notifications.0.contains_key(&key(MAX_NOTIFICATIONS as i64)));
+        assert!(notifications.0.contains_key(&key(9 * 24 * 60)));
+    }
+}
@@ -0,0 +1,269 @@
+//! Resize and crop images to the screen before they are set as the wallpaper,
+//! because some desktops scale images poorly.
+//!
+//! Processed images are written to the [`APPLIED_DIR`] folder, so the cached originals are kept.
+#![cfg(not(target_os = "android"))]
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    services::TEMP_FILE_EXT,
+    signals::{CropStrategy, WallpaperMode},
+};
+use anyhow::{Context, Result};
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView};
+use tokio::fs;
+
+/// The name of the folder (in the app's cache folder) that holds processed images.
+pub const APPLIED_DIR: &str = "applied";
+
+/// The number of processed images kept in the [`APPLIED_DIR`].
+///
+/// This allows a different image on each monitor.
+const MAX_APPLIED_IMAGES: usize = 8;
+
+/// The JPEG quality of processed images.
+const JPEG_QUALITY: u8 = 90;
+
+/// The maximum size (in pixels) of the downscaled copy used to find the most detailed region.
+const SALIENCY_SIZE: u32 = 256;
+
+/// The largest difference between the image's and the screen's aspect ratio
+/// (as a factor) that [`WallpaperMode::Auto`] still crops.
+const MAX_CROP_RATIO: f64 = 1.3;
+
+/// The mode picked by [`WallpaperMode::Auto`] for an image, and why.
+#[derive(Debug, Clone, Copy)]
+pub struct AutoMode {
+    pub mode: WallpaperMode,
+    pub reason: &'static str,
+}
+
+impl Display for AutoMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "chose {:?} mode because {}", self.mode, self.reason)
+    }
+}
+
+/// Pick the mode for an image of the given `size` on a screen of the given size (if known).
+///
+/// Images are centered if they would be upscaled more than twice,
+/// cropped if their aspect ratio is close to the screen's, and fitted otherwise
+/// (e.g. portrait images on a landscape screen).
+pub fn pick_auto_mode(size: (u32, u32), screen: Option<(u32, u32)>) -> AutoMode {
+    let (width, height) = size;
+    let ratio = f64::from(width) / f64::from(height.max(1));
+    let Some((screen_width, screen_height)) = screen else {
+        return if ratio < 1.0 {
+            AutoMode {
+                mode: WallpaperMode::Fit,
+                reason: "the image is portrait",
+            }
+        } else {
+            AutoMode {
+                mode: WallpaperMode::Crop,
+                reason: "the screen size is unknown",
+            }
+        };
+    };
+    if width.saturating_mul(2) <= screen_width && height.saturating_mul(2) <= screen_height {
+        return AutoMode {
+            mode: WallpaperMode::Center,
+            reason: "the image is much smaller than the screen",
+        };
+    }
+    let screen_ratio = f64::from(screen_width) / f64::from(screen_height.max(1));
+    if ratio.max(screen_ratio) / ratio.min(screen_ratio).max(f64::MIN_POSITIVE) <= MAX_CROP_RATIO {
+        AutoMode {
+            mode: WallpaperMode::Crop,
+            reason: "its aspect ratio is close to the screen's",
+        }
+    } else {
+        AutoMode {
+            mode: WallpaperMode::Fit,
+            reason: "cropping would cut off too much of it",
+        }
+    }
+}
+
+/// Get the start of the `window`-sized range in `profile` with the greatest sum.
+fn best_window(profile: &[u64], window: usize) -> usize {
+    if window >= profile.len() {
+        return 0;
+    }
+    let mut sum: u64 = profile[..window].iter().sum();
+    let (mut best, mut best_sum) = (0, sum);
+    for start in 1..=(profile.len() - window) {
+        sum = sum + profile[start + window - 1] - profile[start - 1];
+        if sum > best_sum {
+            (best, best_sum) = (start, sum);
+        }
+    }
+    best
+}
+
+/// Get the offset of the most detailed `crop_width` x `crop_height` region of `img`.
+///
+/// Detail is measured as the magnitude of the brightness gradient.
+/// The region only slides along one axis, because it always spans the other one.
+fn saliency_offset(img: &DynamicImage, crop_width: u32, crop_height: u32) -> (u32, u32) {
+    let (width, height) = img.dimensions();
+    let small = img
+        .resize(SALIENCY_SIZE, SALIENCY_SIZE, FilterType::Triangle)
+        .to_luma8();
+    let (small_width, small_height) = small.dimensions();
+    let mut columns = vec![0u64; small_width as usize];
+    let mut rows = vec![0u64; small_height as usize];
+    for y in 1..small_height.saturating_sub(1) {
+        for x in 1..small_width.saturating_sub(1) {
+            let dx = small[(x + 1, y)][0].abs_diff(small[(x - 1, y)][0]);
+            let dy = small[(x, y + 1)][0].abs_diff(small[(x, y - 1)][0]);
+            let energy = u64::from(dx) + u64::from(dy);
+            columns[x as usize] += energy;
+            rows[y as usize] += energy;
+        }
+    }
+    let scale = |len: u32, small_len: u32, full_len: u32| {
+        (u64::from(len) * u64::from(small_len) / u64::from(full_len.max(1))) as usize
+    };
+    let unscale = |offset: usize, small_len: u32, full_len: u32| {
+        (offset as u64 * u64::from(full_len) / u64::from(small_len.max(1))) as u32
+    };
+    if crop_width < width {
+        let window = scale(crop_width, small_width, width);
+        let x = unscale(best_window(&columns, window), small_width, width);
+        (x.min(width - crop_width), 0)
+    } else {
+        let window = scale(crop_height, small_height, height);
+        let y = unscale(best_window(&rows, window), small_height, height);
+        (0, y.min(height.saturating_sub(crop_height)))
+    }
+}
+
+/// Crop `img` to the aspect ratio of `width` x `height`, then resize it to exactly that size.
+fn crop_to_fill(img: &DynamicImage, width: u32, height: u32, crop: CropStrategy) -> DynamicImage {
+    let (img_width, img_height) = img.dimensions();
+    // compare aspect ratios without floating point error
+    let (crop_width, crop_height) =
+        if u64::from(img_width) * u64::from(height) > u64::from(width) * u64::from(img_height) {
+            let crop_width = u64::from(img_height) * u64::from(width) / u64::from(height);
+            (crop_width as u32, img_height)
+        } else {
+            let crop_height = u64::from(img_width) * u64::from(height) / u64::from(width);
+            (img_width, crop_height as u32)
+        };
+    let (x, y) = match crop {
+        CropStrategy::Center => ((img_width - crop_width) / 2, (img_height - crop_height) / 2),
+        CropStrategy::Saliency => saliency_offset(img, crop_width, crop_height),
+    };
+    img.crop_imm(x, y, crop_width, crop_height)
+        .resize_exact(width, height, FilterType::Lanczos3)
+}
+
+/// Fit the image at `src` to a `width` x `height` screen and save the result to `dest`.
+///
+/// If `fit` is true, then the whole image is kept (the result may be smaller than the screen).
+/// Otherwise, the image is cropped to fill the screen using the given `crop` strategy.
+///
+/// This is CPU-bound, so it should be run with `spawn_blocking()`.
+pub fn process_image(
+    src: &Path,
+    dest: &Path,
+    width: u32,
+    height: u32,
+    fit: bool,
+    crop: CropStrategy,
+) -> Result<()> {
+    let img = image::open(src).with_context(|| format!("Failed to decode image {src:?}"))?;
+    let img = if fit {
+        img.resize(width, height, FilterType::Lanczos3)
+    } else {
+        crop_to_fill(&img, width, height, crop)
+    };
+    // write to a temp file first, so an interrupted write never leaves a partial image
+    let mut temp_path = dest.as_os_str().to_owned();
+    temp_path.push(format!(".{TEMP_FILE_EXT}"));
+    let file = std::fs::File::create(&temp_path)
+        .with_context(|| format!("Failed to create processed image {dest:?}"))?;
+    DynamicImage::ImageRgb8(img.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(file, JPEG_QUALITY))
+        .with_context(|| format!("Failed to encode processed image {dest:?}"))?;
+    std::fs::rename(&temp_path, dest)
+        .with_context(|| format!("Failed to move processed image to {dest:?}"))
+}
+
+/// Mark a previously processed image as recently used, so it is not pruned.
+pub fn touch(path: &Path) -> Result<()> {
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()))
+        .with_context(|| format!("Failed to update modification time of {path:?}"))
+}
+
+/// Remove all but the most recently used images in the `applied_dir`.
+pub async fn prune_applied(applied_dir: &Path) -> Result<()> {
+    let mut images: Vec<(SystemTime, PathBuf)> = vec![];
+    let mut entries = fs::read_dir(applied_dir)
+        .await
+        .with_context(|| "Failed to read processed images folder contents.")?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .with_context(|| "Failed to traverse processed images folder")?
+    {
+        let modified = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        images.push((modified, entry.path()));
+    }
+    images.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in images.into_iter().skip(MAX_APPLIED_IMAGES) {
+        fs::remove_file(&path)
+            .await
+            .with_context(|| format!("Failed to remove processed image {path:?}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crop_to_fill, pick_auto_mode, saliency_offset};
+    use crate::signals::{CropStrategy, WallpaperMode};
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+    #[test]
+    fn saliency_crop_keeps_detail() {
+        // a flat image with a checkered pattern on its right side
+        let img = RgbImage::from_fn(300, 100, |x, y| {
+            if x >= 200 && (x / 4 + y / 4) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let img = DynamicImage::ImageRgb8(img);
+        let (x, y) = saliency_offset(&img, 100, 100);
+        assert!(x >= 180, "crop starts at {x}");
+        assert_eq!(y, 0);
+        let cropped = crop_to_fill(&img, 50, 50, CropStrategy::Center);
+        assert_eq!(cropped.dimensions(), (50, 50));
+    }
+
+    #[test]
+    fn auto_mode() {
+        let screen = Some((1920, 1080));
+        let pick = |size| pick_auto_mode(size, screen).mode;
+        assert!(matches!(pick((3840, 2160)), WallpaperMode::Crop));
+        assert!(matches!(pick((1600, 1200)), WallpaperMode::Fit));
+        // portrait images, like some of NASA's
+        assert!(matches!(pick((2000, 3000)), WallpaperMode::Fit));
+        assert!(matches!(pick((800, 500)), WallpaperMode::Center));
+    }
+}
@@ -0,0 +1,387 @@
+//! The scheduler refreshes image services and changes the desktop wallpaper
+//! at a user-configured time of day, without any interaction from the UI.
+#![cfg(not(target_os = "android"))]
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    common::check_err,
+    notification_center::{NotificationActor, NotificationUpdate},
+    services::{settings::SettingsChanged, AutoRefresh, ImageServiceActor},
+    set_wallpaper::WallpaperActor,
+    settings_center::{get_settings, SettingsActor},
+    signals::{
+        NotificationAlert, NotificationSeverity, NotificationStatus, RefreshSchedule, SetWallpaper,
+        WallpaperSelection,
+    },
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, NaiveTime, TimeDelta, TimeZone};
+use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler, Notifiable};
+use rinf::debug_print;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs, spawn,
+    task::JoinHandle,
+    time::{sleep, Duration},
+};
+
+/// The name of the file (in the app's cache folder) that records when the schedule last ran.
+const SCHEDULER_STATE_FILE: &str = "scheduler.json";
+
+/// The longest time that [`sleep_until_local()`] sleeps before checking the clock again.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Tells the [`SchedulerActor`] to refresh services and set the wallpaper now.
+pub struct RunSchedule;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SchedulerState {
+    /// The time of the last run, formatted as RFC 3339.
+    last_run: Option<String>,
+}
+
+impl SchedulerState {
+    async fn load(app_cache_dir: &Path) -> Self {
+        let path = app_cache_dir.join(SCHEDULER_STATE_FILE);
+        if !path.exists() {
+            return Self::default();
+        }
+        check_err(
+            fs::read_to_string(path)
+                .await
+                .with_context(|| "Failed to read scheduler state")
+                .and_then(|text| {
+                    serde_json::from_str(&text)
+                        .with_context(|| "Failed to deserialize scheduler state")
+                }),
+        )
+        .unwrap_or_default()
+    }
+
+    async fn save(&self, app_cache_dir: &Path) -> Result<()> {
+        fs::write(
+            app_cache_dir.join(SCHEDULER_STATE_FILE),
+            serde_json::to_string(self)
+                .with_context(|| "Failed to convert scheduler state to JSON")?,
+        )
+        .await
+        .with_context(|| "Failed to write scheduler state")
+    }
+
+    fn last_run(&self) -> Option<DateTime<Local>> {
+        self.last_run
+            .as_ref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Local))
+    }
+}
+
+/// Get the time of day of the `schedule`.
+///
+/// Invalid times are rejected when the settings are saved, so this only fails for
+/// settings that were not validated.
+fn get_schedule_time(schedule: &RefreshSchedule) -> Result<NaiveTime> {
+    NaiveTime::from_hms_opt(schedule.hour.into(), schedule.minute.into(), 0).ok_or(anyhow!(
+        "The scheduled time {:02}:{:02} is not a valid time of day",
+        schedule.hour,
+        schedule.minute
+    ))
+}
+
+/// Get the scheduled `time` on the same day as `now`.
+///
+/// If `time` does not exist on that day (skipped by a daylight saving transition),
+/// then the following hour is used instead.
+fn scheduled_on_day<Tz: TimeZone>(now: &DateTime<Tz>, time: NaiveTime) -> DateTime<Tz> {
+    let naive = now.date_naive().and_time(time);
+    let tz = now.timezone();
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(naive + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .unwrap_or_else(|| tz.from_utc_datetime(&naive))
+}
+
+/// Get the most recent scheduled `time` at or before `now`.
+pub(crate) fn previous_run<Tz: TimeZone>(now: &DateTime<Tz>, time: NaiveTime) -> DateTime<Tz> {
+    let today = scheduled_on_day(now, time);
+    if today <= *now {
+        today
+    } else {
+        scheduled_on_day(&(now.clone() - TimeDelta::days(1)), time)
+    }
+}
+
+/// Get the next scheduled `time` after `now`.
+pub(crate) fn next_run<Tz: TimeZone>(now: &DateTime<Tz>, time: NaiveTime) -> DateTime<Tz> {
+    let today = scheduled_on_day(now, time);
+    if today > *now {
+        today
+    } else {
+        scheduled_on_day(&(now.clone() + TimeDelta::days(1)), time)
+    }
+}
+
+/// Wait until the local time reaches `time`.
+///
+/// Tokio's timers stop while the system is suspended, so sleeping until `time` at once
+/// would wake up late by however long the system slept.
+/// Instead, this sleeps in short steps and checks the clock after each.
+pub(crate) async fn sleep_until_local(time: DateTime<Local>) {
+    loop {
+        let remaining = (time - Local::now()).to_std().unwrap_or_default();
+        if remaining.is_zero() {
+            break;
+        }
+        sleep(remaining.min(MAX_SLEEP)).await;
+    }
+}
+
+pub struct SchedulerActor {
+    app_cache_dir: PathBuf,
+    scheduler_addr: Address<Self>,
+    image_service: Address<ImageServiceActor>,
+    wallpaper: Address<WallpaperActor>,
+    notification_center: Address<NotificationActor>,
+    settings: Address<SettingsActor>,
+    timer: Option<JoinHandle<()>>,
+}
+
+impl Actor for SchedulerActor {}
+
+impl SchedulerActor {
+    pub fn new(
+        scheduler_addr: Address<Self>,
+        app_cache_dir: PathBuf,
+        image_service: Address<ImageServiceActor>,
+        wallpaper: Address<WallpaperActor>,
+        notification_center: Address<NotificationActor>,
+        settings: Address<SettingsActor>,
+    ) -> Self {
+        Self {
+            app_cache_dir,
+            scheduler_addr,
+            image_service,
+            wallpaper,
+            notification_center,
+            settings,
+            timer: None,
+        }
+    }
+
+    /// (Re)start the timer that triggers scheduled runs.
+    ///
+    /// If the `schedule` is disabled (or its time is invalid), then the timer is only stopped.
+    pub(crate) async fn restart_timer(&mut self, schedule: &RefreshSchedule) {
+        if let Some(timer) = self.timer.take() {
+            timer.abort();
+        }
+        if !schedule.enabled {
+            return;
+        }
+        let Ok(time) = check_err(get_schedule_time(schedule)) else {
+            return;
+        };
+        let last_run = SchedulerState::load(&self.app_cache_dir).await.last_run();
+        self.timer = Some(spawn(Self::run_timer(
+            self.scheduler_addr.clone(),
+            time,
+            last_run,
+        )));
+    }
+
+    async fn run_timer(
+        mut scheduler_addr: Address<Self>,
+        time: NaiveTime,
+        last_run: Option<DateTime<Local>>,
+    ) {
+        // catch up if the app was not running at the last scheduled time
+        let mut missed = last_run.is_none_or(|last| last < previous_run(&Local::now(), time));
+        loop {
+            if !missed {
+                let next = next_run(&Local::now(), time);
+                debug_print!("Next scheduled refresh at {next}");
+                sleep_until_local(next).await;
+            }
+            missed = false;
+            if let Ok(result) = check_err(
+                scheduler_addr
+                    .send(RunSchedule)
+                    .await
+                    .map_err(|e| anyhow!("Failed to run scheduled refresh: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+        }
+    }
+
+    async fn notify(&mut self, notification: NotificationAlert) {
+        if let Ok(result) = check_err(
+            self.notification_center
+                .send(NotificationUpdate(notification))
+                .await
+                .map_err(|e| anyhow!("Failed to send notification: {e:?}")),
+        ) {
+            let _ = check_err(result);
+        }
+    }
+}
+
+#[async_trait]
+impl Notifiable<SettingsChanged> for SchedulerActor {
+    async fn notify(&mut self, msg: SettingsChanged, _context: &MsgContext<Self>) {
+        if msg.old.schedule != msg.new.schedule {
+            self.restart_timer(&msg.new.schedule).await;
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<RunSchedule> for SchedulerActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, _msg: RunSchedule, _context: &MsgContext<Self>) -> Self::Result {
+        let settings = get_settings(&mut self.settings).await?;
+        debug_print!("Running scheduled refresh");
+
+        let mut newest = None;
+        let services = settings.schedule.services.iter().copied();
+        for service in services.filter(|service| settings.is_service_enabled(*service)) {
+            let result = self
+                .image_service
+                .send(AutoRefresh { service })
+                .await
+                .map_err(|e| anyhow!("Failed to refresh {} cache: {e:?}", service.as_str()));
+            // refresh errors are already reported by the image service,
+            // and skipped refreshes of failing services are only logged
+            if let Ok(Ok(images)) = check_err(result) {
+                if newest.is_none() {
+                    newest = images.into_iter().next();
+                }
+            }
+        }
+        SchedulerState {
+            last_run: Some(Local::now().to_rfc3339()),
+        }
+        .save(&self.app_cache_dir)
+        .await?;
+
+        let Some(path) = newest else {
+            self.notify(NotificationAlert {
+                id: NotificationAlert::next_id(),
+                title: "Scheduled wallpaper change".to_string(),
+                body: "No images available from the scheduled services".to_string(),
+                percent: 1.0,
+                severity: NotificationSeverity::Warning,
+                status: NotificationStatus::default(),
+            })
+            .await;
+            return Ok(());
+        };
+        let path = path.to_string_lossy().to_string();
+        let notification = match self
+            .wallpaper
+            .send(SetWallpaper {
+                selected: WallpaperSelection {
+                    path: path.clone(),
+                    mode: Some(settings.mode),
+                    monitor: None,
+                },
+            })
+            .await
+            .map_err(|e| anyhow!("{e:?}"))
+        {
+            Ok(Ok(auto)) => NotificationAlert {
+                id: NotificationAlert::next_id(),
+                title: "Scheduled wallpaper change".to_string(),
+                body: match auto {
+                    Some(auto) => format!("Set the desktop wallpaper to {path} ({auto})"),
+                    None => format!("Set the desktop wallpaper to {path}"),
+                },
+                percent: 1.0,
+                severity: NotificationSeverity::Info,
+                status: NotificationStatus::default(),
+            },
+            Ok(Err(e)) | Err(e) => NotificationAlert {
+                id: NotificationAlert::next_id(),
+                title: "Scheduled wallpaper change".to_string(),
+                body: format!("Failed to set the desktop wallpaper: {e:?}"),
+                percent: 1.0,
+                severity: NotificationSeverity::Error,
+                status: NotificationStatus::default(),
+            },
+        };
+        self.notify(notification).await;
+        Ok(())
+    }
+}
+
+// Creates and spawns the actors in the async system.
+pub async fn create_actors(
+    app_cache_dir: PathBuf,
+    image_service: Address<ImageServiceActor>,
+    wallpaper: Address<WallpaperActor>,
+    notification_center: Address<NotificationActor>,
+    mut settings: Address<SettingsActor>,
+) -> Result<Address<SchedulerActor>> {
+    // Create actor contexts.
+    let scheduler_context = MsgContext::new();
+    let scheduler_addr = scheduler_context.address();
+
+    // Spawn actors.
+    let mut actor = SchedulerActor::new(
+        scheduler_addr.clone(),
+        app_cache_dir,
+        image_service,
+        wallpaper,
+        notification_center,
+        settings.clone(),
+    );
+    let schedule = get_settings(&mut settings).await?.schedule;
+    actor.restart_timer(&schedule).await;
+    spawn(scheduler_context.run(actor));
+    Ok(scheduler_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_schedule_time, next_run, previous_run};
+    use crate::signals::RefreshSchedule;
+    use chrono::{NaiveTime, TimeZone, Utc};
+
+    #[test]
+    fn schedule_boundaries() {
+        let time = NaiveTime::from_hms_opt(8, 30, 0).unwrap();
+        let before = Utc.with_ymd_and_hms(2025, 3, 10, 7, 0, 0).unwrap();
+        let after = Utc.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap();
+        let today = Utc.with_ymd_and_hms(2025, 3, 10, 8, 30, 0).unwrap();
+        let yesterday = Utc.with_ymd_and_hms(2025, 3, 9, 8, 30, 0).unwrap();
+        let tomorrow = Utc.with_ymd_and_hms(2025, 3, 11, 8, 30, 0).unwrap();
+
+        assert_eq!(previous_run(&before, time), yesterday);
+        assert_eq!(next_run(&before, time), today);
+        assert_eq!(previous_run(&after, time), today);
+        assert_eq!(next_run(&after, time), tomorrow);
+        // exactly on time counts as the current run
+        assert_eq!(previous_run(&today, time), today);
+        assert_eq!(next_run(&today, time), tomorrow);
+    }
+
+    #[test]
+    fn rejects_invalid_schedule_time() {
+        let schedule = |hour, minute| RefreshSchedule {
+            hour,
+            minute,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_schedule_time(&schedule(8, 30)).unwrap(),
+            NaiveTime::from_hms_opt(8, 30, 0).unwrap()
+        );
+        assert!(get_schedule_time(&schedule(24, 0)).is_err());
+        assert!(get_schedule_time(&schedule(8, 60)).is_err());
+    }
+}
@@ -5,15 +5,29 @@
 //! To build a solid app, do not communicate by sharing memory;
 //! instead, share memory by communicating.
 
-use std::{path::PathBuf, time::Duration};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use super::{condense_duration, get_service_metadata_name, get_service_url, UpdateResources};
+use super::{
+    condense_duration, get_app_cache_dir, get_service_metadata_name, get_service_url,
+    health::load_health, thumbnails::ensure_thumbnail, UpdateResources, TEMP_FILE_EXT,
+};
+#[cfg(all(unix, not(target_os = "android")))]
+use crate::control::{self, ControlRequest};
 use crate::{
-    common::check_err,
+    common::{check_err, Clock, SystemClock},
     notification_center::{NotificationActor, NotificationUpdate},
-    signals::{ImageService, NotificationAlert, NotificationSeverity, NotificationStatus, Refresh},
+    settings_center::{get_settings, SettingsActor},
+    signals::{
+        ImageService, NotificationAlert, NotificationSeverity, NotificationStatus, Refresh,
+        ServiceHealth,
+    },
 };
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use futures_util::{StreamExt, TryFutureExt};
 use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler};
 use reqwest::{header::CONTENT_LENGTH, Client};
@@ -25,6 +39,17 @@ use tokio::{fs, io::AsyncWriteExt, spawn, time::Instant};
 pub struct ImageServiceActor {
     pub(super) app_cache_dir: PathBuf,
     pub(super) notification_center: Address<NotificationActor>,
+    pub(super) settings: Address<SettingsActor>,
+    /// The health of each service.
+    pub(super) health: Vec<ServiceHealth>,
+    pub(super) clock: Arc<dyn Clock>,
+    /// The notification ID of each service's current (or last) operation,
+    /// so that errors are shown in the operation's notification.
+    pub(super) notification_ids: HashMap<ImageService, u64>,
+    /// The control socket of a running daemon, which changes the cached files instead.
+    ///
+    /// The app then only lists what the daemon cached.
+    pub(super) daemon: Option<PathBuf>,
 }
 
 // This defines our `Actor` as an actor in the async system.
@@ -35,15 +60,30 @@ impl ImageServiceActor {
         service_addr: Address<Self>,
         app_cache_dir: PathBuf,
         notification_center: Address<NotificationActor>,
+        settings: Address<SettingsActor>,
+        health: Vec<ServiceHealth>,
+        clock: Arc<dyn Clock>,
+        daemon: Option<PathBuf>,
     ) -> Self {
         spawn(Self::listen_to_refresh(service_addr.clone()));
-        spawn(Self::listen_to_wallpaper_mode(service_addr));
+        spawn(Self::listen_to_verify_cache(service_addr.clone()));
+        spawn(Self::listen_to_health_refresh(service_addr));
         ImageServiceActor {
             app_cache_dir,
             notification_center,
+            settings,
+            health,
+            clock,
+            notification_ids: HashMap::new(),
+            daemon,
         }
     }
 
+    /// Does a running daemon change the cached files instead of this actor?
+    pub(super) fn is_attached(&self) -> bool {
+        self.daemon.is_some()
+    }
+
     async fn listen_to_refresh(mut service_addr: Address<Self>) {
         // Spawn an asynchronous task to listen for signals from Dart.
         let receiver = Refresh::get_dart_signal_receiver();
@@ -87,6 +127,9 @@ impl ImageServiceActor {
         total_steps: u8,
         mut notification: NotificationAlert,
     ) -> Result<usize> {
+        if self.is_attached() {
+            bail!("The background service did not download {display_id}");
+        }
         let response = client.get(url).send().await?;
         let total_size = if let Some(v) = response.headers().get(CONTENT_LENGTH) {
             let as_str = v.to_str()?;
@@ -119,9 +162,19 @@ impl ImageServiceActor {
             );
             self.check_notify_send_error(notification.clone()).await?;
         }
+        if let Some(total_size) = total_size {
+            if downloaded < total_size {
+                return Err(anyhow!(
+                    "Download of {display_id} was truncated ({downloaded}/{total_size} bytes)"
+                ));
+            }
+        }
+
+        // write to a temp file first, so an interrupted write never leaves a partial image
+        let temp_path = format!("{cache_path}.{TEMP_FILE_EXT}");
         check_err(
             check_err(
-                fs::File::create(cache_path)
+                fs::File::create(&temp_path)
                     .map_err(|e| anyhow!("Failed to create cache file for {cache_path}: {e:?}"))
                     .await,
             )?
@@ -129,16 +182,47 @@ impl ImageServiceActor {
             .map_err(|e| anyhow!("Failed to write data to file {cache_path}: {e:?}"))
             .await,
         )?;
+        check_err(
+            fs::rename(&temp_path, cache_path)
+                .await
+                .map_err(|e| anyhow!("Failed to move temp file to {cache_path}: {e:?}")),
+        )?;
         Ok(total_size.unwrap_or(downloaded))
     }
 
+    /// Get the path to the given `cache_file`'s thumbnail (as a string).
+    ///
+    /// Missing thumbnails are generated on demand.
+    /// Failures are not fatal because the gallery can fall back to the full-size image,
+    /// so an empty string is returned instead.
+    pub(super) async fn get_thumbnail(&self, cache_file: &Path) -> String {
+        check_err(ensure_thumbnail(cache_file).await)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Start a new operation on the `service`, which gets its own notification.
+    ///
+    /// Returns the ID of the operation's notification.
+    pub(super) fn start_notification(&mut self, service: ImageService) -> u64 {
+        let id = NotificationAlert::next_id();
+        self.notification_ids.insert(service, id);
+        id
+    }
+
     pub(super) async fn notify_err<T>(
         &mut self,
         result: Result<T>,
         service: ImageService,
     ) -> Result<T> {
         if let Err(e) = &result {
+            let id = self
+                .notification_ids
+                .get(&service)
+                .copied()
+                .unwrap_or_else(NotificationAlert::next_id);
             self.check_notify_send_error(NotificationAlert {
+                id,
                 title: format!("{} images", service.as_str()),
                 body: [e.to_string(), e.root_cause().to_string()].join("\n"),
                 percent: 1.0,
@@ -149,39 +233,70 @@ impl ImageServiceActor {
         }
         result
     }
-}
 
-#[async_trait]
-impl Handler<Refresh> for ImageServiceActor {
-    /// Result of the message processing.
-    type Result = Result<()>;
+    /// Let the daemon listening on `socket_path` refresh the cache of the `message`'s service,
+    /// then list the images that it cached.
+    #[cfg(all(unix, not(target_os = "android")))]
+    async fn refresh_through_daemon(
+        &mut self,
+        socket_path: &Path,
+        message: Refresh,
+    ) -> Result<Vec<PathBuf>> {
+        let service = message.service;
+        self.start_notification(service);
+        let result = control::forward(
+            socket_path,
+            ControlRequest::Refresh {
+                service,
+                reset: message.reset,
+            },
+        )
+        .await;
+        // the daemon recorded the outcome
+        self.reload_health(service).await;
+        self.notify_err(result, service).await?;
+        self.refresh(Refresh {
+            service,
+            reset: false,
+        })
+        .await
+    }
 
-    /// Processes a message.
-    async fn handle(&mut self, message: Refresh, _context: &MsgContext<Self>) -> Self::Result {
+    /// Refresh the cache of the `message`'s service.
+    ///
+    /// On success, this returns the paths to the cached images (ordered as listed by the service).
+    async fn refresh(&mut self, message: Refresh) -> Result<Vec<PathBuf>> {
         // set up output mechanisms (in-app notifications and terminal out)
         let service = message.service;
         let service_name = service.as_str();
         let debug_title = format!("{} images", service_name);
         debug_print!("Getting {debug_title}");
+        let network = self.get_network_options().await?;
         let mut res = UpdateResources::new(
             self.app_cache_dir.join(service_name),
             NotificationAlert {
+                id: self.start_notification(service),
                 title: debug_title.to_string(),
                 body: "Checking cache".to_string(),
                 percent: 0.0,
                 severity: NotificationSeverity::Info,
                 status: NotificationStatus::default(),
             },
+            &network,
         )?;
         self.check_notify_send_error(res.notification.clone())
             .await?;
         let timer = Instant::now();
+        // the folder is missing if the service was disabled when the app started
+        fs::create_dir_all(&res.app_cache_dir)
+            .await
+            .with_context(|| "Failed to create cache folder")?;
 
         // get cache state
-        let metadata_file_name = get_service_metadata_name(&service);
+        let metadata_file_name = get_service_metadata_name(&service, self.clock.now());
         let cached_metadata = res.app_cache_dir.join(&metadata_file_name);
 
-        res.text = if !message.reset && cached_metadata.exists() {
+        res.text = if self.is_attached() || (!message.reset && cached_metadata.exists()) {
             check_err(
                 fs::read_to_string(&cached_metadata)
                     .await
@@ -231,45 +346,18 @@ impl Handler<Refresh> for ImageServiceActor {
             ImageService::Spotlight => self.cache_updates_spotlight(&mut res).await,
         }?;
 
-        // dispose outdated cached images
-        let mut removed: u8 = 0;
-        let mut entries = self
-            .notify_err(
-                fs::read_dir(&res.app_cache_dir)
-                    .await
-                    .with_context(|| "Failed to read cache folder contents."),
+        // dispose outdated cached images (unless the daemon does that)
+        let removed = if self.is_attached() {
+            0
+        } else {
+            self.prune_cache(
                 service,
+                &res.app_cache_dir,
+                &metadata_file_name,
+                &cached_images,
             )
-            .await?;
-        while let Some(entry) = entries
-            .next_entry()
-            .await
-            .with_context(|| "Failed to traverse cache dir")?
-        {
-            let path = entry.path();
-            if !path.is_file() {
-                continue;
-            }
-            let filename = path
-                .file_name()
-                .ok_or(anyhow!("Failed to get cached file name"))?
-                .to_string_lossy()
-                .to_string();
-            if filename == metadata_file_name {
-                continue;
-            }
-            if !cached_images.contains(&filename) {
-                removed += 1;
-                debug_print!("Deleting outdated cache file {:?}", path);
-                self.notify_err(
-                    fs::remove_file(path)
-                        .await
-                        .with_context(|| "Failed to delete outdated Nasa cache file"),
-                    service,
-                )
-                .await?;
-            }
-        }
+            .await?
+        };
 
         // finish up
         let elapsed = condense_duration(timer.elapsed());
@@ -282,7 +370,7 @@ impl Handler<Refresh> for ImageServiceActor {
                         .with_base(size::Base::Base10)
                         .to_string(),
                 ),
-                removed: Some(removed),
+                removed: Some(removed.try_into().unwrap_or(u32::MAX)),
                 elapsed: Some(elapsed),
             };
             res.notification.body =
@@ -294,26 +382,51 @@ impl Handler<Refresh> for ImageServiceActor {
             };
             res.notification.body = "Cache is already updated".to_string();
         }
-        self.check_notify_send_error(res.notification).await
+        self.check_notify_send_error(res.notification).await?;
+        Ok(cached_images
+            .iter()
+            .map(|name| res.app_cache_dir.join(name))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Handler<Refresh> for ImageServiceActor {
+    /// Result of the message processing.
+    ///
+    /// On success, this is the paths to the cached images (ordered as listed by the service).
+    type Result = Result<Vec<PathBuf>>;
+
+    /// Processes a message.
+    async fn handle(&mut self, message: Refresh, _context: &MsgContext<Self>) -> Self::Result {
+        let service = message.service;
+        let settings = get_settings(&mut self.settings).await?;
+        if !settings.is_service_enabled(service) {
+            bail!("{} is disabled in the settings", service.as_str());
+        }
+        #[cfg(all(unix, not(target_os = "android")))]
+        if let Some(socket_path) = self.daemon.clone() {
+            return self.refresh_through_daemon(&socket_path, message).await;
+        }
+        let result = self.refresh(message).await;
+        self.record_health(service, &result).await;
+        result
     }
 }
 
 // Creates and spawns the actors in the async system.
-pub async fn create_actors(notification_center: Address<NotificationActor>) -> Result<()> {
+// If a `daemon` is listening on the given control socket, then it refreshes the cache instead.
+pub async fn create_actors(
+    notification_center: Address<NotificationActor>,
+    mut settings: Address<SettingsActor>,
+    daemon: Option<PathBuf>,
+) -> Result<Address<ImageServiceActor>> {
     // Create actor contexts.
     let img_service_ctx = MsgContext::new();
     let img_service_addr = img_service_ctx.address();
 
-    let cache_dir = dirs::cache_dir()
-        .ok_or(anyhow!(
-            "Failed to detect system cache folder; Is this running on a desktop?"
-        ))?
-        .join("Daily-Wallpaper-Images");
-    for service in [
-        ImageService::Bing,
-        ImageService::Nasa,
-        ImageService::Spotlight,
-    ] {
+    let cache_dir = get_app_cache_dir()?;
+    for service in get_settings(&mut settings).await?.enabled_services() {
         let app_cache_dir = cache_dir.join(service.as_str());
         if !app_cache_dir.exists() {
             fs::create_dir_all(&app_cache_dir).await?;
@@ -321,7 +434,16 @@ pub async fn create_actors(notification_center: Address<NotificationActor>) -> R
     }
 
     // Spawn actors.
-    let actor = ImageServiceActor::new(img_service_addr, cache_dir, notification_center);
+    let health = load_health(&cache_dir).await;
+    let actor = ImageServiceActor::new(
+        img_service_addr.clone(),
+        cache_dir,
+        notification_center,
+        settings,
+        health,
+        Arc::new(SystemClock),
+        daemon,
+    );
     spawn(img_service_ctx.run(actor));
-    Ok(())
+    Ok(img_service_addr)
 }
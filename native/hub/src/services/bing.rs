@@ -1,6 +1,6 @@
 //! Any logic that is specific to Bing Image of the Day service goes here.
 
-use super::{ImageServiceActor, UpdateResources, DATE_FILE_FMT};
+use super::{ImageServiceActor, ImageSource, UpdateResources, DATE_FILE_FMT};
 use crate::{
     common::check_err,
     signals::{DailyImage, ImageList, ImageService},
@@ -28,6 +28,26 @@ fn parse_date(date: &str) -> Result<NaiveDate> {
         .with_context(|| "Failed to parse Bing picture's date.")
 }
 
+impl BingImage {
+    fn source(&self) -> Result<ImageSource> {
+        let date = parse_date(&self.start_date)?.format(DATE_FILE_FMT);
+        Ok(ImageSource {
+            name: format!("{date}.jpg"),
+            url: format!("https://bing.com{}", self.url),
+            description: self.copyright.clone(),
+        })
+    }
+}
+
+pub(super) fn get_image_sources(text: &str) -> Result<Vec<ImageSource>> {
+    serde_json::from_str::<BingImages>(text)
+        .with_context(|| "Failed to deserialize Bing images' response payload.")?
+        .images
+        .iter()
+        .map(BingImage::source)
+        .collect()
+}
+
 impl ImageServiceActor {
     pub(super) async fn cache_updates_bing(
         &mut self,
@@ -47,6 +67,7 @@ impl ImageServiceActor {
                 .iter()
                 .map(|i| DailyImage {
                     url: String::new(),
+                    thumbnail: String::new(),
                     date: check_err(parse_date(&i.start_date))
                         .map(|d| d.format(DATE_FILE_FMT).to_string())
                         .unwrap_or(i.start_date.clone()),
@@ -59,9 +80,7 @@ impl ImageServiceActor {
         let mut image_names = vec![];
 
         for (i, img) in images.into_iter().enumerate() {
-            let date = parse_date(&img.start_date)?.format(DATE_FILE_FMT);
-
-            let name = format!("{date}.jpg");
+            let ImageSource { name, url, .. } = img.source()?;
             image_names.push(name.clone());
             let file_name = res.app_cache_dir.join(&name);
             let cache_path = file_name.to_string_lossy().to_string();
@@ -70,7 +89,7 @@ impl ImageServiceActor {
                 let result = self
                     .download_file(
                         &res.client,
-                        format!("https://bing.com{}", img.url).as_str(),
+                        &url,
                         &cache_path,
                         &name,
                         (res.total_steps + res.total_images) as u8,
@@ -80,8 +99,9 @@ impl ImageServiceActor {
                 res.downloaded += self.notify_err(result, ImageService::Bing).await?;
             }
             image_list.images[i].url = file_name.to_string_lossy().to_string();
+            image_list.images[i].thumbnail = self.get_thumbnail(&file_name).await;
             image_list.send_signal_to_dart();
-            res.notification.body = format!("Processed {date}");
+            res.notification.body = format!("Processed {}", image_list.images[i].date);
             res.notification.percent =
                 ((i + res.total_steps) as f32) / ((res.total_images + res.total_steps) as f32);
             self.check_notify_send_error(res.notification.clone())
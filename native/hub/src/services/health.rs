@@ -0,0 +1,207 @@
+//! Tracks the outcome of each service's refreshes,
+//! so that automatic refreshes can back off from a service that keeps failing
+//! instead of producing a new error notification every time.
+
+use std::path::{Path, PathBuf};
+
+use super::ImageServiceActor;
+use crate::{
+    common::check_err,
+    signals::{ImageService, Refresh, ServiceHealth, ServiceHealthRefresh},
+};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, TimeDelta};
+use messages::prelude::{async_trait, Address, Context as MsgContext, Handler};
+use rinf::{DartSignal, RustSignal};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// The name of the file (in the app's cache folder) that records the health of each service.
+const HEALTH_FILE: &str = "health.json";
+
+/// How long automatic refreshes are skipped after the first failure.
+///
+/// This doubles with every consecutive failure, up to [`MAX_BACKOFF`].
+const MIN_BACKOFF: TimeDelta = TimeDelta::minutes(15);
+
+/// The longest time that automatic refreshes are skipped.
+const MAX_BACKOFF: TimeDelta = TimeDelta::hours(24);
+
+/// Tells the [`ImageServiceActor`] to refresh a `service`,
+/// unless it is backing off because the service failed recently.
+///
+/// Use this instead of [`Refresh`] for refreshes that the user did not ask for.
+pub struct AutoRefresh {
+    pub service: ImageService,
+}
+
+/// Get how long to back off after the given number of consecutive `failures`.
+fn backoff(failures: u32) -> TimeDelta {
+    let factor = 2i32.saturating_pow(failures.saturating_sub(1));
+    MIN_BACKOFF
+        .checked_mul(factor)
+        .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HealthState {
+    services: Vec<ServiceHealth>,
+}
+
+/// Read the health of every service cached in `app_cache_dir`.
+///
+/// Services without a cached record are considered healthy.
+pub(super) async fn load_health(app_cache_dir: &Path) -> Vec<ServiceHealth> {
+    let path = app_cache_dir.join(HEALTH_FILE);
+    let cached = if path.exists() {
+        check_err(
+            fs::read_to_string(path)
+                .await
+                .with_context(|| "Failed to read service health")
+                .and_then(|text| {
+                    serde_json::from_str::<HealthState>(&text)
+                        .with_context(|| "Failed to deserialize service health")
+                }),
+        )
+        .unwrap_or_default()
+    } else {
+        HealthState::default()
+    };
+    ImageService::ALL
+        .into_iter()
+        .map(|service| {
+            cached
+                .services
+                .iter()
+                .find(|health| health.service == service)
+                .cloned()
+                .unwrap_or_else(|| ServiceHealth::new(service))
+        })
+        .collect()
+}
+
+impl ImageServiceActor {
+    pub async fn listen_to_health_refresh(mut service_addr: Address<Self>) {
+        let receiver = ServiceHealthRefresh::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            let _ = service_addr.send(dart_signal.message).await;
+        }
+    }
+
+    fn get_health(&mut self, service: ImageService) -> &mut ServiceHealth {
+        let index = self
+            .health
+            .iter()
+            .position(|health| health.service == service)
+            .unwrap_or_else(|| {
+                self.health.push(ServiceHealth::new(service));
+                self.health.len() - 1
+            });
+        &mut self.health[index]
+    }
+
+    /// Record the `result` of refreshing a `service` and send the updated health to Dart.
+    pub(super) async fn record_health<T>(&mut self, service: ImageService, result: &Result<T>) {
+        let now = self.clock.now().with_timezone(&Local);
+        let health = self.get_health(service);
+        match result {
+            Ok(_) => {
+                health.last_success = Some(now.to_rfc3339());
+                health.failures = 0;
+                health.retry_after = None;
+            }
+            Err(e) => {
+                health.last_failure = Some(now.to_rfc3339());
+                health.failures = health.failures.saturating_add(1);
+                health.last_error = Some(format!("{e:#}"));
+                health.retry_after = Some((now + backoff(health.failures)).to_rfc3339());
+            }
+        }
+        health.clone().send_signal_to_dart();
+        let _ = check_err(self.save_health().await);
+    }
+
+    /// Read the health that a running daemon recorded for a `service`,
+    /// and send it to Dart.
+    #[cfg(all(unix, not(target_os = "android")))]
+    pub(super) async fn reload_health(&mut self, service: ImageService) {
+        self.health = load_health(&self.app_cache_dir).await;
+        self.get_health(service).clone().send_signal_to_dart();
+    }
+
+    async fn save_health(&self) -> Result<()> {
+        let state = HealthState {
+            services: self.health.clone(),
+        };
+        fs::write(
+            self.app_cache_dir.join(HEALTH_FILE),
+            serde_json::to_string(&state)
+                .with_context(|| "Failed to convert service health to JSON")?,
+        )
+        .await
+        .with_context(|| "Failed to write service health")
+    }
+}
+
+#[async_trait]
+impl Handler<ServiceHealthRefresh> for ImageServiceActor {
+    type Result = ();
+
+    async fn handle(
+        &mut self,
+        _msg: ServiceHealthRefresh,
+        _context: &MsgContext<Self>,
+    ) -> Self::Result {
+        for health in &self.health {
+            health.clone().send_signal_to_dart();
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<AutoRefresh> for ImageServiceActor {
+    /// Same as the result of a [`Refresh`].
+    ///
+    /// This is an error (that is not shown as a notification) if the refresh was skipped.
+    type Result = Result<Vec<PathBuf>>;
+
+    async fn handle(&mut self, msg: AutoRefresh, context: &MsgContext<Self>) -> Self::Result {
+        let service = msg.service;
+        let now = self.clock.now();
+        let health = self.get_health(service);
+        let retry_after = health
+            .retry_after
+            .as_ref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+        if let Some(retry_after) = retry_after {
+            if retry_after > now {
+                bail!(
+                    "Skipping {} refresh until {retry_after} after {} consecutive failures",
+                    service.as_str(),
+                    health.failures
+                );
+            }
+        }
+        self.handle(
+            Refresh {
+                service,
+                reset: false,
+            },
+            context,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, MAX_BACKOFF, MIN_BACKOFF};
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(backoff(1), MIN_BACKOFF);
+        assert_eq!(backoff(3), MIN_BACKOFF * 4);
+        assert_eq!(backoff(8), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+}
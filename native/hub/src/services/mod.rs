@@ -1,20 +1,47 @@
 mod actor;
 pub mod bing;
+mod health;
 pub mod nasa;
+mod prune;
 pub mod settings;
 pub mod spotlight;
-use std::{path::PathBuf, time::Duration};
+pub mod thumbnails;
+mod verify;
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
 pub use actor::{create_actors, ImageServiceActor};
-use anyhow::Result;
-use chrono::Local;
-use reqwest::{Client, ClientBuilder};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Utc};
+use chrono_tz::{
+    America::{Los_Angeles, New_York},
+    Tz,
+};
+pub use health::AutoRefresh;
+pub use prune::Prune;
+use reqwest::{Client, ClientBuilder, Proxy};
+use tokio::fs;
 
-use crate::signals::{ImageService, NotificationAlert};
+use crate::signals::{ImageService, NetworkOptions, NotificationAlert};
+
+/// Get the folder where all cached data (images, metadata and settings) is stored.
+pub fn get_app_cache_dir() -> Result<PathBuf> {
+    Ok(dirs::cache_dir()
+        .ok_or(anyhow!(
+            "Failed to detect system cache folder; Is this running on a desktop?"
+        ))?
+        .join("Daily-Wallpaper-Images"))
+}
 
 pub fn get_service_url(service: &ImageService) -> &'static str {
     match service {
-        ImageService::Bing => "https://www.bing.com/HPImageArchive.aspx?format=js&idx=0&n=14",
+        // the market is fixed, because the service day (see `get_service_day()`) depends on it
+        ImageService::Bing => {
+            "https://www.bing.com/HPImageArchive.aspx?format=js&idx=0&n=14&mkt=en-US"
+        }
         ImageService::Nasa => "https://www.nasa.gov/feeds/iotd-feed/",
         ImageService::Spotlight => "https://fd.api.iris.microsoft.com/v4/api/selection?&placement=88000820&bcnt=4&country=us&locale=en-us&fmt=json",
     }
@@ -23,17 +50,226 @@ pub fn get_service_url(service: &ImageService) -> &'static str {
 const DATE_FILE_FMT: &str = "%F";
 const GENERIC_FILE_FMT: &str = "info.json";
 
-pub fn get_service_metadata_name(service: &ImageService) -> String {
-    if service.is_daily() {
-        let now = Local::now().date_naive();
-        let ext = if matches!(service, ImageService::Nasa) {
-            "xml"
-        } else {
-            "json"
-        };
-        format!("{}.{ext}", now.format(DATE_FILE_FMT))
-    } else {
-        GENERIC_FILE_FMT.to_string()
+/// The extension appended to a cache file's name while it is being written.
+pub(crate) const TEMP_FILE_EXT: &str = "part";
+
+/// When a daily service's "day" changes (i.e. when it publishes a new image).
+pub struct ServiceDay {
+    /// The service's time zone.
+    pub time_zone: Tz,
+    /// The hour (in the service's time zone) when a new image is published.
+    pub publish_hour: u32,
+}
+
+/// Get when the `service`'s day changes.
+///
+/// Returns `None` if the `service` does not publish images daily.
+pub fn get_service_day(service: &ImageService) -> Option<ServiceDay> {
+    match service {
+        // the US market's image changes at midnight, Pacific time
+        ImageService::Bing => Some(ServiceDay {
+            time_zone: Los_Angeles,
+            publish_hour: 0,
+        }),
+        // the image of the day is published during the work day, Eastern time
+        ImageService::Nasa => Some(ServiceDay {
+            time_zone: New_York,
+            publish_hour: 12,
+        }),
+        ImageService::Spotlight => None,
+    }
+}
+
+/// Get the `service`'s date at the given time (`now`).
+///
+/// Returns `None` if the `service` does not publish images daily.
+pub fn get_service_date(service: &ImageService, now: DateTime<Utc>) -> Option<NaiveDate> {
+    let day = get_service_day(service)?;
+    let local = now.with_timezone(&day.time_zone) - TimeDelta::hours(day.publish_hour.into());
+    Some(local.date_naive())
+}
+
+fn get_service_metadata_ext(service: &ImageService) -> &'static str {
+    match service {
+        ImageService::Nasa => "xml",
+        _ => "json",
+    }
+}
+
+/// Get the name of the file that caches the `service`'s metadata at the given time (`now`).
+///
+/// For daily services, this changes when the service publishes a new image.
+pub fn get_service_metadata_name(service: &ImageService, now: DateTime<Utc>) -> String {
+    match get_service_date(service, now) {
+        Some(date) => format!(
+            "{}.{}",
+            date.format(DATE_FILE_FMT),
+            get_service_metadata_ext(service)
+        ),
+        None => GENERIC_FILE_FMT.to_string(),
+    }
+}
+
+/// List the images cached for a `service`, newest first.
+///
+/// Images of daily services are ordered by the date in their name.
+/// Otherwise, the time that an image was downloaded is used.
+pub async fn list_cached_images(
+    app_cache_dir: &Path,
+    service: &ImageService,
+) -> Result<Vec<PathBuf>> {
+    list_all_cached_images(app_cache_dir, std::slice::from_ref(service)).await
+}
+
+/// List the images cached for all of the `services`, newest first.
+///
+/// Like [`list_cached_images()`], but the images of different services are merged by date.
+pub async fn list_all_cached_images(
+    app_cache_dir: &Path,
+    services: &[ImageService],
+) -> Result<Vec<PathBuf>> {
+    let mut images = vec![];
+    for service in services {
+        images.extend(find_cached_images(&app_cache_dir.join(service.as_str())).await?);
+    }
+    images.sort_by_key(|(date, modified, _)| Reverse((*date, *modified)));
+    Ok(images.into_iter().map(|(_, _, path)| path).collect())
+}
+
+/// Find the images in a `service_dir`, with the date that each image is sorted by
+/// and the time that it was downloaded.
+///
+/// Images without a date in their name are dated by when they were downloaded.
+async fn find_cached_images(service_dir: &Path) -> Result<Vec<(NaiveDate, SystemTime, PathBuf)>> {
+    if !service_dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut images = vec![];
+    let mut entries = fs::read_dir(service_dir)
+        .await
+        .with_context(|| "Failed to read cache folder contents.")?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .with_context(|| "Failed to traverse cache dir")?
+    {
+        let path = entry.path();
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if !path.is_file() || ["json", "xml", TEMP_FILE_EXT].contains(&ext.as_str()) {
+            continue;
+        }
+        let modified = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let date = path
+            .file_stem()
+            .and_then(|stem| NaiveDate::parse_from_str(&stem.to_string_lossy(), DATE_FILE_FMT).ok())
+            .unwrap_or_else(|| DateTime::<Local>::from(modified).date_naive());
+        images.push((date, modified, path));
+    }
+    Ok(images)
+}
+
+/// A cached image's file name and the URL it is downloaded from.
+#[derive(Debug)]
+pub(crate) struct ImageSource {
+    pub name: String,
+    pub url: String,
+    /// The image's description (including copyright info) as listed by the service.
+    pub description: String,
+}
+
+/// A service's metadata file (and the images listed in it) found in the cache.
+pub(crate) struct CachedMetadata {
+    pub file_name: String,
+    pub sources: Vec<ImageSource>,
+}
+
+/// Get the sources listed in the most recent metadata file cached in `service_dir`.
+///
+/// Returns `None` if there is no cached metadata for the `service`.
+pub(crate) async fn find_cached_metadata(
+    service: &ImageService,
+    service_dir: &Path,
+) -> Result<Option<CachedMetadata>> {
+    let metadata_ext = Some(get_service_metadata_ext(service).as_ref());
+    let mut latest: Option<PathBuf> = None;
+    let mut entries = fs::read_dir(service_dir)
+        .await
+        .with_context(|| "Failed to read cache folder contents.")?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .with_context(|| "Failed to traverse cache dir")?
+    {
+        let path = entry.path();
+        // metadata for daily services are named after the date, so they sort chronologically
+        if path.is_file()
+            && path.extension() == metadata_ext
+            && latest
+                .as_ref()
+                .is_none_or(|l| l.file_name() < path.file_name())
+        {
+            latest = Some(path);
+        }
+    }
+    let Some(path) = latest else {
+        return Ok(None);
+    };
+    let text = fs::read_to_string(&path)
+        .await
+        .with_context(|| "Failed to read cached metadata")?;
+    Ok(Some(CachedMetadata {
+        file_name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        sources: get_image_sources(service, &text)?,
+    }))
+}
+
+/// Get the service that the cached image at `path` came from.
+///
+/// Returns `None` if the image is not in a service's cache folder.
+pub(crate) fn find_image_service(path: &Path) -> Option<ImageService> {
+    let service_dir = path.parent()?;
+    ImageService::ALL
+        .into_iter()
+        .find(|service| service_dir.file_name() == Some(service.as_str().as_ref()))
+}
+
+/// Get the description of the cached image at `path` from its service's cached metadata.
+///
+/// Returns `None` if the image is not listed in the cached metadata.
+pub(crate) async fn find_image_description(path: &Path) -> Result<Option<String>> {
+    let (Some(service_dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(None);
+    };
+    let Some(service) = find_image_service(path) else {
+        return Ok(None);
+    };
+    Ok(find_cached_metadata(&service, service_dir)
+        .await?
+        .and_then(|metadata| {
+            metadata
+                .sources
+                .into_iter()
+                .find(|source| name == source.name.as_str())
+        })
+        .map(|source| source.description))
+}
+
+/// Get the sources of all images listed in a `service`'s metadata `text`.
+pub(crate) fn get_image_sources(service: &ImageService, text: &str) -> Result<Vec<ImageSource>> {
+    match service {
+        ImageService::Bing => bing::get_image_sources(text),
+        ImageService::Nasa => nasa::get_image_sources(text),
+        ImageService::Spotlight => spotlight::get_image_sources(text),
     }
 }
 
@@ -49,18 +285,32 @@ struct UpdateResources {
 }
 
 impl UpdateResources {
-    pub fn new(app_cache_dir: PathBuf, notification: NotificationAlert) -> Result<Self> {
+    pub fn new(
+        app_cache_dir: PathBuf,
+        notification: NotificationAlert,
+        network: &NetworkOptions,
+    ) -> Result<Self> {
+        let mut client = ClientBuilder::new().user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ));
+        if network.timeout > 0 {
+            // a large download may take longer than the timeout, as long as data keeps arriving
+            let timeout = Duration::from_secs(network.timeout.into());
+            client = client.connect_timeout(timeout).read_timeout(timeout);
+        }
+        if !network.proxy.is_empty() {
+            client = client.proxy(
+                Proxy::all(&network.proxy)
+                    .with_context(|| format!("Invalid proxy URL {}", network.proxy))?,
+            );
+        }
         Ok(Self {
             app_cache_dir,
             notification,
             text: Default::default(),
-            client: ClientBuilder::new()
-                .user_agent(concat!(
-                    env!("CARGO_PKG_NAME"),
-                    "/",
-                    env!("CARGO_PKG_VERSION")
-                ))
-                .build()?,
+            client: client.build()?,
             downloaded: Default::default(),
             updated_images: Default::default(),
             total_images: Default::default(),
@@ -80,3 +330,61 @@ pub fn condense_duration(duration: Duration) -> String {
         format!("{seconds} s")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{get_service_metadata_name, ImageService};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn metadata_name_follows_service_day() {
+        let at = |h, m| Utc.with_ymd_and_hms(2025, 1, 2, h, m, 0).unwrap();
+        // Bing's day starts at midnight, Pacific time (08:00 UTC)
+        let bing = ImageService::Bing;
+        assert_eq!(
+            get_service_metadata_name(&bing, at(7, 59)),
+            "2025-01-01.json"
+        );
+        assert_eq!(
+            get_service_metadata_name(&bing, at(8, 0)),
+            "2025-01-02.json"
+        );
+        // NASA's day starts at noon, Eastern time (17:00 UTC)
+        let nasa = ImageService::Nasa;
+        assert_eq!(
+            get_service_metadata_name(&nasa, at(16, 59)),
+            "2025-01-01.xml"
+        );
+        assert_eq!(
+            get_service_metadata_name(&nasa, at(17, 0)),
+            "2025-01-02.xml"
+        );
+        let spotlight = ImageService::Spotlight;
+        assert_eq!(get_service_metadata_name(&spotlight, at(0, 0)), "info.json");
+    }
+
+    #[test]
+    fn service_day_follows_daylight_saving_time() {
+        let at = |h, m| Utc.with_ymd_and_hms(2025, 7, 2, h, m, 0).unwrap();
+        // midnight, Pacific daylight time (07:00 UTC)
+        let bing = ImageService::Bing;
+        assert_eq!(
+            get_service_metadata_name(&bing, at(6, 59)),
+            "2025-07-01.json"
+        );
+        assert_eq!(
+            get_service_metadata_name(&bing, at(7, 0)),
+            "2025-07-02.json"
+        );
+        // noon, Eastern daylight time (16:00 UTC)
+        let nasa = ImageService::Nasa;
+        assert_eq!(
+            get_service_metadata_name(&nasa, at(15, 59)),
+            "2025-07-01.xml"
+        );
+        assert_eq!(
+            get_service_metadata_name(&nasa, at(16, 0)),
+            "2025-07-02.xml"
+        );
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::path::Path;
 
-use super::{actor::ImageServiceActor, UpdateResources, DATE_FILE_FMT};
+use super::{actor::ImageServiceActor, ImageSource, UpdateResources, DATE_FILE_FMT};
 use crate::{
     common::check_err,
     signals::{DailyImage, ImageList, ImageService},
@@ -42,6 +42,34 @@ fn parse_date(date: &str) -> Result<NaiveDate> {
         .with_context(|| "Failed to parse NASA picture's date.")
 }
 
+impl NasaItem {
+    fn source(&self) -> Result<ImageSource> {
+        let date = parse_date(&self.pub_date[5..16])?.format(DATE_FILE_FMT);
+        let name = format!(
+            "{date}.{}",
+            Path::new(Url::parse(&self.enclosure.url)?.path())
+                .extension()
+                .ok_or(anyhow!("Failed to find image MIME type from NASA URL."))?
+                .to_string_lossy()
+        );
+        Ok(ImageSource {
+            name,
+            url: self.enclosure.url.clone(),
+            description: self.description.clone(),
+        })
+    }
+}
+
+pub(super) fn get_image_sources(text: &str) -> Result<Vec<ImageSource>> {
+    quick_xml::de::from_str::<NasaFeed>(text)
+        .with_context(|| "Failed to deserialize NASA images' response payload.")?
+        .channel
+        .item
+        .iter()
+        .map(NasaItem::source)
+        .collect()
+}
+
 impl ImageServiceActor {
     pub(super) async fn cache_updates_nasa(
         &mut self,
@@ -62,6 +90,7 @@ impl ImageServiceActor {
                 .iter()
                 .map(|i| DailyImage {
                     url: String::new(),
+                    thumbnail: String::new(),
                     date: check_err(parse_date(&i.pub_date[5..16]))
                         .map(|d| d.format(DATE_FILE_FMT).to_string())
                         .unwrap_or(i.pub_date.clone()),
@@ -74,18 +103,8 @@ impl ImageServiceActor {
 
         let mut image_names = vec![];
         for (i, item) in images.into_iter().enumerate() {
-            let date = parse_date(&item.pub_date[5..16])?.format(DATE_FILE_FMT);
-            let name = format!(
-                "{date}.{}",
-                self.notify_err(
-                    Path::new(Url::parse(&item.enclosure.url)?.path())
-                        .extension()
-                        .ok_or(anyhow!("Failed to find image MIME type from NASA URL.")),
-                    ImageService::Nasa,
-                )
-                .await?
-                .to_string_lossy()
-            );
+            let ImageSource { name, url, .. } =
+                self.notify_err(item.source(), ImageService::Nasa).await?;
             image_names.push(name.clone());
             let file_name = res.app_cache_dir.join(&name);
             let cache_path = file_name.to_string_lossy().to_string();
@@ -94,7 +113,7 @@ impl ImageServiceActor {
                 let result = self
                     .download_file(
                         &res.client,
-                        &item.enclosure.url,
+                        &url,
                         &cache_path,
                         &name,
                         (res.total_steps + res.total_images) as u8,
@@ -104,8 +123,9 @@ impl ImageServiceActor {
                 res.downloaded += self.notify_err(result, ImageService::Nasa).await?;
             }
             image_list.images[i].url = file_name.to_string_lossy().to_string();
+            image_list.images[i].thumbnail = self.get_thumbnail(&file_name).await;
             image_list.send_signal_to_dart();
-            res.notification.body = format!("Processed {date}");
+            res.notification.body = format!("Processed {}", image_list.images[i].date);
             res.notification.percent =
                 ((i + res.total_steps) as f32) / ((res.total_images + res.total_steps) as f32);
             self.check_notify_send_error(res.notification.clone())
@@ -0,0 +1,111 @@
+//! Removal of cached files that are no longer listed by a service.
+
+use std::path::Path;
+
+use super::{find_cached_metadata, thumbnails::prune_thumbnails, ImageServiceActor};
+use crate::{settings_center::get_settings, signals::ImageService};
+use anyhow::{anyhow, Context, Result};
+use messages::prelude::{async_trait, Context as MsgContext, Handler};
+use rinf::debug_print;
+use tokio::fs;
+
+/// Remove a service's cached files that are not listed in its most recently cached metadata.
+///
+/// Unlike [`Refresh`](crate::signals::Refresh), this does not fetch anything from the service.
+pub struct Prune {
+    pub service: ImageService,
+}
+
+impl ImageServiceActor {
+    /// Delete all files in `service_dir` (and its thumbnails) that are
+    /// neither the `metadata_file_name` nor one of the `cached_images`.
+    ///
+    /// Returns the number of files removed.
+    pub(super) async fn prune_cache(
+        &mut self,
+        service: ImageService,
+        service_dir: &Path,
+        metadata_file_name: &str,
+        cached_images: &[String],
+    ) -> Result<usize> {
+        let mut removed = 0;
+        let mut entries = self
+            .notify_err(
+                fs::read_dir(service_dir)
+                    .await
+                    .with_context(|| "Failed to read cache folder contents."),
+                service,
+            )
+            .await?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .with_context(|| "Failed to traverse cache dir")?
+        {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let filename = path
+                .file_name()
+                .ok_or(anyhow!("Failed to get cached file name"))?
+                .to_string_lossy()
+                .to_string();
+            if filename == metadata_file_name {
+                continue;
+            }
+            if !cached_images.contains(&filename) {
+                removed += 1;
+                debug_print!("Deleting outdated cache file {:?}", path);
+                self.notify_err(
+                    fs::remove_file(path)
+                        .await
+                        .with_context(|| "Failed to delete outdated cache file"),
+                    service,
+                )
+                .await?;
+            }
+        }
+        removed += self
+            .notify_err(prune_thumbnails(service_dir, cached_images).await, service)
+            .await?;
+        Ok(removed)
+    }
+}
+
+#[async_trait]
+impl Handler<Prune> for ImageServiceActor {
+    /// On success, this is the number of files removed.
+    type Result = Result<usize>;
+
+    async fn handle(&mut self, msg: Prune, _context: &MsgContext<Self>) -> Self::Result {
+        let service = msg.service;
+        self.start_notification(service);
+        if !get_settings(&mut self.settings)
+            .await?
+            .is_service_enabled(service)
+        {
+            debug_print!("{} is disabled; nothing to prune", service.as_str());
+            return Ok(0);
+        }
+        let service_dir = self.app_cache_dir.join(service.as_str());
+        let metadata = self
+            .notify_err(find_cached_metadata(&service, &service_dir).await, service)
+            .await?;
+        let Some(metadata) = metadata else {
+            // without metadata, every cached image would be considered outdated
+            debug_print!(
+                "No cached metadata for {}; nothing to prune",
+                service.as_str()
+            );
+            return Ok(0);
+        };
+        let cached_images = metadata
+            .sources
+            .into_iter()
+            .map(|source| source.name)
+            .collect::<Vec<_>>();
+        self.prune_cache(service, &service_dir, &metadata.file_name, &cached_images)
+            .await
+    }
+}
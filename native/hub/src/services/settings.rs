@@ -1,74 +1,279 @@
+use std::{collections::BTreeMap, path::Path};
+
 use crate::{
-    services::ImageServiceActor,
-    signals::{WallpaperMode, WallpaperModeCache},
+    services::{ImageServiceActor, TEMP_FILE_EXT},
+    settings_center::get_settings,
+    signals::{
+        Caption, DayCycle, DesktopNotifications, ImageProcessing, ImageService, NetworkOptions,
+        RefreshSchedule, ServiceEntry, Slideshow, WallpaperBackendConfig, WallpaperMode,
+    },
 };
-use anyhow::{Context, Result};
-use messages::prelude::{async_trait, Address, Context as MsgContext, Handler};
-use rinf::{debug_print, DartSignal, RustSignal};
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, bail, Context, Result};
+use rinf::debug_print;
+use serde_json::{Map, Value};
 use tokio::fs;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Settings {
-    mode: WallpaperMode,
+pub use crate::signals::Settings;
+
+/// The name of the settings file in the app's cache folder.
+pub(crate) const SETTINGS_FILE: &str = "settings.json";
+
+/// The current version of the settings file's layout.
+pub(crate) const SETTINGS_VERSION: u32 = 1;
+
+/// Tells an actor that keeps some settings in memory (or derived state, like timers)
+/// that the settings were changed.
+pub struct SettingsChanged {
+    pub old: Settings,
+    pub new: Settings,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            version: SETTINGS_VERSION,
             mode: WallpaperMode::Fit,
+            schedule: RefreshSchedule::default(),
+            slideshow: Slideshow::default(),
+            monitors: BTreeMap::new(),
+            processing: ImageProcessing::default(),
+            caption: Caption::default(),
+            backend: WallpaperBackendConfig::default(),
+            day_cycle: DayCycle::default(),
+            network: NetworkOptions::default(),
+            services: ImageService::ALL
+                .map(|service| ServiceEntry {
+                    service,
+                    enabled: true,
+                })
+                .to_vec(),
+            desktop_notifications: DesktopNotifications::default(),
+            notification_rate: 10,
         }
     }
 }
 
-impl ImageServiceActor {
-    pub async fn listen_to_wallpaper_mode(mut service_addr: Address<Self>) {
-        let receiver = WallpaperModeCache::get_dart_signal_receiver();
-        while let Some(dart_signal) = receiver.recv().await {
-            let _ = service_addr.send(dart_signal.message).await;
+impl Settings {
+    /// Parse the settings from the `text` of a settings file.
+    ///
+    /// Files written before the settings were versioned (version 0) have the same layout,
+    /// only with fewer fields, which get their defaults.
+    /// A change of the layout must bump [`SETTINGS_VERSION`] and convert older files here.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let mut value: Value =
+            serde_json::from_str(text).with_context(|| "Failed to parse cached settings")?;
+        let settings = value
+            .as_object_mut()
+            .ok_or(anyhow!("The cached settings are not a JSON object"))?;
+        let version = settings
+            .get("version")
+            .and_then(Value::as_u64)
+            .unwrap_or_default();
+        if version > u64::from(SETTINGS_VERSION) {
+            debug_print!(
+                "Settings were written by a newer version ({version}); unknown fields are kept"
+            );
         }
+        settings.insert("version".to_string(), SETTINGS_VERSION.into());
+        let mut settings: Self = serde_json::from_value(value)
+            .with_context(|| "Failed to deserialize cached settings")?;
+        settings.services = settings.get_services();
+        Ok(settings)
     }
-}
 
-#[async_trait]
-impl Handler<WallpaperModeCache> for ImageServiceActor {
-    type Result = Result<()>;
-
-    async fn handle(
-        &mut self,
-        msg: WallpaperModeCache,
-        _context: &MsgContext<Self>,
-    ) -> Self::Result {
-        let cached_settings = self.app_cache_dir.join("settings.json");
-
-        let mut settings = if let Some(mode) = msg.mode {
-            debug_print!("Setting mode to {mode:?}");
-            Settings { mode }
-        } else {
-            Settings::default()
-        };
+    /// Get all services in their display order, and whether each is enabled.
+    ///
+    /// Duplicates are removed and services missing from the settings are appended (enabled).
+    pub fn get_services(&self) -> Vec<ServiceEntry> {
+        let mut services: Vec<ServiceEntry> = Vec::with_capacity(ImageService::ALL.len());
+        for entry in &self.services {
+            if !services.iter().any(|e| e.service == entry.service) {
+                services.push(*entry);
+            }
+        }
+        for service in ImageService::ALL {
+            if !services.iter().any(|e| e.service == service) {
+                services.push(ServiceEntry {
+                    service,
+                    enabled: true,
+                });
+            }
+        }
+        services
+    }
 
-        // if settings' cache does not exist or the mode is set with Some value
-        if !cached_settings.exists() || msg.mode.is_some() {
-            fs::write(
-                &cached_settings,
-                serde_json::to_string(&settings)
-                    .with_context(|| "Failed to convert settings data to JSON")?,
-            )
-            .await?;
+    /// Get the enabled services in their display order.
+    pub fn enabled_services(&self) -> Vec<ImageService> {
+        self.get_services()
+            .into_iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.service)
+            .collect()
+    }
+
+    pub fn is_service_enabled(&self, service: ImageService) -> bool {
+        self.get_services()
+            .iter()
+            .any(|entry| entry.service == service && entry.enabled)
+    }
+
+    /// Check the values that are not restricted by their types alone.
+    pub fn validate(&self) -> Result<()> {
+        let times = std::iter::once(("the schedule", self.schedule.hour, self.schedule.minute))
+            .chain(
+                self.day_cycle
+                    .slots
+                    .iter()
+                    .map(|slot| (slot.name.as_str(), slot.hour, slot.minute)),
+            );
+        for (name, hour, minute) in times {
+            if hour > 23 || minute > 59 {
+                bail!("The time {hour:02}:{minute:02} of {name} is not a valid time of day");
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the settings cached in `app_cache_dir`.
+    ///
+    /// If no settings were cached yet, then the default settings are returned.
+    pub async fn load(app_cache_dir: &Path) -> Result<Self> {
+        let cached_settings = app_cache_dir.join(SETTINGS_FILE);
+        if !cached_settings.exists() {
+            return Ok(Self::default());
         }
+        let cached_data = fs::read_to_string(cached_settings).await?;
+        Self::from_json(&cached_data)
+    }
 
-        // if mode is not set (None), then get the value from cache and send it back to dart
-        if msg.mode.is_none() {
-            debug_print!("Getting WallpaperMode from cache");
-            let cached_data = fs::read_to_string(cached_settings).await?;
-            settings = serde_json::from_str(&cached_data)
-                .with_context(|| "Failed to deserialize cached settings")?;
+    /// Validate the settings and write them to the cache in `app_cache_dir`.
+    ///
+    /// If the cached settings were written by a newer version, then their version and
+    /// the fields unknown to this version are kept, so that version can still read them.
+    pub async fn save(&self, app_cache_dir: &Path) -> Result<()> {
+        self.validate()?;
+        let path = app_cache_dir.join(SETTINGS_FILE);
+        let mut value = serde_json::to_value(self)
+            .with_context(|| "Failed to convert settings data to JSON")?;
+        if let (Some(newer), Some(known)) = (read_newer(&path).await, value.as_object_mut()) {
+            for (key, field) in newer {
+                if key == "version" {
+                    known.insert(key, field);
+                } else {
+                    known.entry(key).or_insert(field);
+                }
+            }
         }
-        let result = WallpaperModeCache {
-            mode: Some(settings.mode),
+        // write to a temp file first, so an interrupted write never leaves partial settings
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(format!(".{TEMP_FILE_EXT}"));
+        fs::write(&temp_path, value.to_string())
+            .await
+            .with_context(|| "Failed to write settings to cache")?;
+        fs::rename(&temp_path, &path)
+            .await
+            .with_context(|| "Failed to move settings to cache")
+    }
+}
+
+/// Read the settings file at `path` (as JSON), if it was written by a newer version.
+async fn read_newer(path: &Path) -> Option<Map<String, Value>> {
+    let text = fs::read_to_string(path).await.ok()?;
+    let Value::Object(settings) = serde_json::from_str(&text).ok()? else {
+        return None;
+    };
+    let version = settings.get("version").and_then(Value::as_u64)?;
+    (version > u64::from(SETTINGS_VERSION)).then_some(settings)
+}
+
+impl ImageServiceActor {
+    /// Get the configured network options.
+    pub(super) async fn get_network_options(&mut self) -> Result<NetworkOptions> {
+        Ok(get_settings(&mut self.settings).await?.network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Settings, SETTINGS_FILE, SETTINGS_VERSION};
+    use crate::common::testing::TempDir;
+    use crate::signals::{ImageService, ServiceEntry, SlideshowOrder, WallpaperMode};
+    use serde_json::{json, Value};
+    use std::fs;
+
+    #[test]
+    fn reads_unversioned_settings() {
+        let text = r#"{
+            "mode": "Crop",
+            "slideshow": {"enabled": true, "interval": 5, "services": ["Nasa"], "order": "Shuffle"}
+        }"#;
+        let settings = Settings::from_json(text).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert!(matches!(settings.mode, WallpaperMode::Crop));
+        assert_eq!(settings.slideshow.interval, 5);
+        assert_eq!(settings.slideshow.order, SlideshowOrder::Shuffle);
+        // fields that did not exist yet get their defaults
+        assert!(!settings.slideshow.per_monitor);
+        assert!(!settings.schedule.enabled);
+        assert_eq!(settings.network.timeout, 0);
+    }
+
+    #[tokio::test]
+    async fn keeps_newer_settings() {
+        let dir = TempDir::new("settings");
+        let newer =
+            json!({"version": SETTINGS_VERSION + 1, "mode": "Crop", "favorites": ["a.jpg"]});
+        fs::write(dir.join(SETTINGS_FILE), newer.to_string()).unwrap();
+        let mut settings = Settings::load(&dir).await.unwrap();
+        settings.mode = WallpaperMode::Tile;
+        settings.save(&dir).await.unwrap();
+        let saved: Value =
+            serde_json::from_str(&fs::read_to_string(dir.join(SETTINGS_FILE)).unwrap()).unwrap();
+
+        assert_eq!(saved["version"], SETTINGS_VERSION + 1);
+        assert_eq!(saved["mode"], "Tile");
+        assert_eq!(saved["favorites"], json!(["a.jpg"]));
+    }
+
+    #[test]
+    fn services_keep_order_and_fill_gaps() {
+        let settings = Settings {
+            services: vec![
+                ServiceEntry {
+                    service: ImageService::Spotlight,
+                    enabled: true,
+                },
+                ServiceEntry {
+                    service: ImageService::Bing,
+                    enabled: false,
+                },
+            ],
+            ..Default::default()
         };
-        result.send_signal_to_dart();
-        Ok(())
+        assert_eq!(
+            settings.enabled_services(),
+            [ImageService::Spotlight, ImageService::Nasa]
+        );
+        assert!(!settings.is_service_enabled(ImageService::Bing));
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        let mut settings = Settings::default();
+        assert!(settings.validate().is_ok());
+        settings.day_cycle.slots[1].hour = 24;
+        assert!(settings.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn does_not_save_invalid_settings() {
+        let dir = TempDir::new("invalid-settings");
+        let mut settings = Settings::default();
+        settings.schedule.minute = 60;
+        let result = settings.save(&dir).await;
+        let saved = dir.join(SETTINGS_FILE).exists();
+
+        assert!(result.is_err());
+        assert!(!saved);
     }
 }
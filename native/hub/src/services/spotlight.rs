@@ -1,6 +1,6 @@
 //! Any logic that is specific to Windows Spotlight Images service goes here.
 
-use super::{actor::ImageServiceActor, UpdateResources};
+use super::{actor::ImageServiceActor, ImageSource, UpdateResources};
 use crate::signals::{DailyImage, ImageList, ImageService};
 use anyhow::{Context, Result};
 use rinf::RustSignal;
@@ -22,15 +22,29 @@ pub struct SpotlightImage {
     pub entity_id: String,
 }
 
+impl SpotlightImage {
+    fn source(&self) -> ImageSource {
+        ImageSource {
+            name: format!("{}.jpg", self.entity_id),
+            url: self.landscape_image.asset.clone(),
+            description: self.description(),
+        }
+    }
+
+    fn description(&self) -> String {
+        self.icon_hover_text
+            .trim_end_matches("\r\nRight-click to learn more")
+            .replace("\r\n", "\n")
+    }
+}
+
 impl From<SpotlightImage> for DailyImage {
     fn from(value: SpotlightImage) -> Self {
         DailyImage {
             url: String::new(),
+            thumbnail: String::new(),
             date: String::new(),
-            description: value
-                .icon_hover_text
-                .trim_end_matches("\r\nRight-click to learn more")
-                .replace("\r\n", "\n"),
+            description: value.description(),
         }
     }
 }
@@ -56,36 +70,45 @@ pub struct SpotlightImages {
     pub batch_response: SpotlightBatchInfo,
 }
 
+fn parse_images(text: &str) -> Result<Vec<SpotlightImage>> {
+    serde_json::from_str::<SpotlightImages>(text)
+        .with_context(|| "Failed to deserialize Windows Spotlight images' response payload.")?
+        .batch_response
+        .items
+        .iter()
+        .map(|item| {
+            serde_json::from_str::<SpotlightItemContent>(item.item.trim_matches('`'))
+                .with_context(|| "Failed to deserialize Windows Spotlight image info")
+                .map(|content| content.ad)
+        })
+        .collect()
+}
+
+pub(super) fn get_image_sources(text: &str) -> Result<Vec<ImageSource>> {
+    Ok(parse_images(text)?
+        .iter()
+        .map(SpotlightImage::source)
+        .collect())
+}
+
 impl ImageServiceActor {
     pub(super) async fn cache_updates_spotlight(
         &mut self,
         res: &mut UpdateResources,
     ) -> Result<Vec<String>> {
-        let items = self
-            .notify_err(
-                serde_json::from_str::<SpotlightImages>(&res.text).with_context(|| {
-                    "Failed to deserialize Windows Spotlight images' response payload."
-                }),
-                ImageService::Spotlight,
-            )
-            .await?
-            .batch_response
-            .items;
-        res.total_images = items.len();
+        let images = self
+            .notify_err(parse_images(&res.text), ImageService::Spotlight)
+            .await?;
+        res.total_images = images.len();
 
         let mut image_list = ImageList {
             images: vec![],
             service: ImageService::Spotlight,
         };
         let mut new_item_ids = vec![];
-        for item in items {
-            let content = serde_json::from_str::<SpotlightItemContent>(item.item.trim_matches('`'))
-                .with_context(|| "Failed to deserialize Windows Spotlight image info")?
-                .ad;
-            new_item_ids.push((
-                format!("{}.jpg", content.entity_id),
-                content.landscape_image.asset.clone(),
-            ));
+        for content in images {
+            let ImageSource { name, url, .. } = content.source();
+            new_item_ids.push((name, url));
             image_list.images.push(content.into());
         }
 
@@ -107,6 +130,7 @@ impl ImageServiceActor {
                 res.downloaded += self.notify_err(result, ImageService::Spotlight).await?;
             }
             image_list.images[i].url = cache_path;
+            image_list.images[i].thumbnail = self.get_thumbnail(&file_name).await;
             // The send method is generated from a marked Protobuf message.
             image_list.send_signal_to_dart();
             res.notification.body = format!("Processed {id}");
@@ -0,0 +1,141 @@
+//! Downscaled copies of cached images used by the gallery view.
+//!
+//! Thumbnails live in a [`THUMBNAIL_DIR`] subfolder of each service's cache folder.
+//! They are named after the cached image they were generated from,
+//! so a missing thumbnail can always be regenerated from its source.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use image::{imageops::FilterType, ImageFormat};
+use rinf::debug_print;
+use tokio::{fs, task::spawn_blocking};
+
+use super::TEMP_FILE_EXT;
+
+/// The name of the subfolder (in a service's cache folder) that holds thumbnails.
+pub const THUMBNAIL_DIR: &str = "thumbs";
+
+/// The maximum width (in pixels) of a generated thumbnail.
+pub const THUMBNAIL_WIDTH: u32 = 400;
+
+/// Get the path to the thumbnail that corresponds to the given `cache_file`.
+pub fn get_thumbnail_path(cache_file: &Path) -> Result<PathBuf> {
+    let name = cache_file
+        .file_name()
+        .ok_or(anyhow!("Failed to get cached file name"))?;
+    let parent = cache_file
+        .parent()
+        .ok_or(anyhow!("Failed to get cached file's folder"))?;
+    Ok(parent.join(THUMBNAIL_DIR).join(name))
+}
+
+/// Get the thumbnail for the given `cache_file`, generating it first if it does not exist.
+pub async fn ensure_thumbnail(cache_file: &Path) -> Result<PathBuf> {
+    let thumbnail = get_thumbnail_path(cache_file)?;
+    if thumbnail.exists() {
+        return Ok(thumbnail);
+    }
+    if let Some(thumb_dir) = thumbnail.parent() {
+        if !thumb_dir.exists() {
+            fs::create_dir_all(thumb_dir)
+                .await
+                .with_context(|| "Failed to create thumbnails folder")?;
+        }
+    }
+    debug_print!("Generating thumbnail for {cache_file:?}");
+    let src = cache_file.to_path_buf();
+    let dest = thumbnail.clone();
+    spawn_blocking(move || -> Result<()> {
+        let img = image::open(&src).with_context(|| format!("Failed to decode image {src:?}"))?;
+        let img = if img.width() > THUMBNAIL_WIDTH {
+            img.resize(THUMBNAIL_WIDTH, u32::MAX, FilterType::Triangle)
+        } else {
+            img
+        };
+        // write to a temp file first, so an interrupted write never leaves a partial thumbnail
+        let format = ImageFormat::from_path(&dest)
+            .with_context(|| format!("Failed to detect thumbnail format of {dest:?}"))?;
+        let mut temp_path = dest.as_os_str().to_owned();
+        temp_path.push(format!(".{TEMP_FILE_EXT}"));
+        img.save_with_format(&temp_path, format)
+            .with_context(|| format!("Failed to save thumbnail {dest:?}"))?;
+        std::fs::rename(&temp_path, &dest)
+            .with_context(|| format!("Failed to move thumbnail to {dest:?}"))
+    })
+    .await
+    .map_err(|e| anyhow!("Failed to join thumbnail generator task: {e:?}"))??;
+    Ok(thumbnail)
+}
+
+/// Remove any thumbnails in `app_cache_dir` that do not correspond to a name in `cached_images`.
+///
+/// Returns the number of thumbnails removed.
+pub async fn prune_thumbnails(app_cache_dir: &Path, cached_images: &[String]) -> Result<usize> {
+    let thumb_dir = app_cache_dir.join(THUMBNAIL_DIR);
+    if !thumb_dir.exists() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    let mut entries = fs::read_dir(&thumb_dir)
+        .await
+        .with_context(|| "Failed to read thumbnails folder contents.")?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .with_context(|| "Failed to traverse thumbnails dir")?
+    {
+        let path = entry.path();
+        let filename = entry.file_name().to_string_lossy().to_string();
+        if path.is_file() && !cached_images.contains(&filename) {
+            debug_print!("Deleting outdated thumbnail {:?}", path);
+            fs::remove_file(path)
+                .await
+                .with_context(|| "Failed to delete outdated thumbnail")?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ensure_thumbnail, prune_thumbnails, THUMBNAIL_DIR, THUMBNAIL_WIDTH};
+    use crate::common::testing::TempDir;
+    use std::fs;
+
+    #[tokio::test]
+    async fn prunes_thumbnails_of_removed_images() {
+        let service_dir = TempDir::new("thumbs");
+        let thumb_dir = service_dir.join(THUMBNAIL_DIR);
+        fs::create_dir_all(&thumb_dir).unwrap();
+        // more than fit in a byte
+        for i in 0..300 {
+            fs::write(thumb_dir.join(format!("{i}.jpg")), b"").unwrap();
+        }
+        let cached_images = vec!["0.jpg".to_string()];
+        let removed = prune_thumbnails(&service_dir, &cached_images).await;
+        let remaining = fs::read_dir(&thumb_dir).unwrap().count();
+        assert_eq!(removed.unwrap(), 299);
+        assert_eq!(remaining, 1);
+    }
+
+    #[tokio::test]
+    async fn generates_thumbnail_without_leftovers() {
+        let service_dir = TempDir::new("thumbs-generate");
+        let image = service_dir.join("2025-01-01.jpg");
+        image::RgbImage::new(THUMBNAIL_WIDTH * 2, 100)
+            .save(&image)
+            .unwrap();
+        let thumbnail = ensure_thumbnail(&image).await.unwrap();
+        let names = fs::read_dir(service_dir.join(THUMBNAIL_DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["2025-01-01.jpg"]);
+        assert_eq!(
+            image::image_dimensions(&thumbnail).unwrap(),
+            (THUMBNAIL_WIDTH, 50)
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Detection and repair of damaged files in the image cache.
+
+use std::path::Path;
+
+use super::{
+    condense_duration, find_cached_metadata, thumbnails::get_thumbnail_path, ImageServiceActor,
+    UpdateResources, TEMP_FILE_EXT,
+};
+#[cfg(all(unix, not(target_os = "android")))]
+use crate::control::{self, ControlRequest};
+use crate::{
+    common::check_err,
+    settings_center::get_settings,
+    signals::{NotificationAlert, NotificationSeverity, NotificationStatus, VerifyCache},
+};
+use anyhow::{anyhow, Context, Result};
+use messages::prelude::{async_trait, Address, Context as MsgContext, Handler};
+use rinf::{debug_print, DartSignal};
+use size::Size;
+use tokio::{fs, task::spawn_blocking, time::Instant};
+
+/// Check if the image at `path` is damaged.
+///
+/// Returns a description of the problem, or `None` if the image can be fully decoded.
+async fn check_image(path: &Path) -> Result<Option<String>> {
+    let metadata = fs::metadata(path)
+        .await
+        .with_context(|| format!("Failed to get metadata of {path:?}"))?;
+    if metadata.len() == 0 {
+        return Ok(Some("file is empty".to_string()));
+    }
+    let path = path.to_path_buf();
+    let decoded = spawn_blocking(move || image::open(path))
+        .await
+        .map_err(|e| anyhow!("Failed to join image decoder task: {e:?}"))?;
+    Ok(decoded.err().map(|e| e.to_string()))
+}
+
+impl ImageServiceActor {
+    pub async fn listen_to_verify_cache(mut service_addr: Address<Self>) {
+        let receiver = VerifyCache::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            debug_print!("verifying image cache");
+            if let Ok(result) = check_err(
+                service_addr
+                    .send(dart_signal.message)
+                    .await
+                    .map_err(|e| anyhow!("Failed to verify image cache: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<VerifyCache> for ImageServiceActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, _msg: VerifyCache, _context: &MsgContext<Self>) -> Self::Result {
+        // the daemon may be downloading into the cache right now
+        #[cfg(all(unix, not(target_os = "android")))]
+        if let Some(socket_path) = &self.daemon {
+            let result = control::forward(socket_path, ControlRequest::Verify).await;
+            self.check_notify_send_error(NotificationAlert {
+                id: NotificationAlert::next_id(),
+                title: "Cache verification".to_string(),
+                body: match &result {
+                    Ok(()) => "Verified by the background service".to_string(),
+                    Err(e) => format!("{e:?}"),
+                },
+                percent: 1.0,
+                severity: match &result {
+                    Ok(()) => NotificationSeverity::Info,
+                    Err(_) => NotificationSeverity::Error,
+                },
+                status: NotificationStatus::default(),
+            })
+            .await?;
+            return result;
+        }
+        let settings = get_settings(&mut self.settings).await?;
+        let mut res = UpdateResources::new(
+            self.app_cache_dir.clone(),
+            NotificationAlert {
+                id: NotificationAlert::next_id(),
+                title: "Cache verification".to_string(),
+                body: "Scanning cache".to_string(),
+                percent: 0.0,
+                severity: NotificationSeverity::Info,
+                status: NotificationStatus::default(),
+            },
+            &settings.network,
+        )?;
+        self.check_notify_send_error(res.notification.clone())
+            .await?;
+        let timer = Instant::now();
+        let mut checked = 0;
+        let mut repaired = 0;
+        let mut removed: usize = 0;
+        let services = settings.enabled_services();
+        res.total_steps = services.len();
+
+        for (step, service) in services.iter().enumerate() {
+            let service_dir = res.app_cache_dir.join(service.as_str());
+            if !service_dir.exists() {
+                continue;
+            }
+            res.notification.body = format!("Scanning {} images", service.as_str());
+            res.notification.percent = (step as f32) / (res.total_steps as f32);
+            self.check_notify_send_error(res.notification.clone())
+                .await?;
+            let sources = check_err(find_cached_metadata(service, &service_dir).await)
+                .ok()
+                .flatten()
+                .map(|metadata| metadata.sources)
+                .unwrap_or_default();
+
+            // collect the folder's contents first, as repairs will write new files into it
+            let mut files = vec![];
+            let mut entries = fs::read_dir(&service_dir)
+                .await
+                .with_context(|| "Failed to read cache folder contents.")?;
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .with_context(|| "Failed to traverse cache dir")?
+            {
+                let path = entry.path();
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+
+            for path in files {
+                let filename = path
+                    .file_name()
+                    .ok_or(anyhow!("Failed to get cached file name"))?
+                    .to_string_lossy()
+                    .to_string();
+                let ext = path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                if ext == TEMP_FILE_EXT {
+                    debug_print!("Deleting orphaned temp file {path:?}");
+                    fs::remove_file(&path)
+                        .await
+                        .with_context(|| "Failed to delete orphaned temp file")?;
+                    removed += 1;
+                    continue;
+                }
+                if ext == "json" || ext == "xml" {
+                    // cached metadata, not an image
+                    continue;
+                }
+                checked += 1;
+                let Some(reason) = check_image(&path).await? else {
+                    continue;
+                };
+                debug_print!("Found damaged cache file {path:?}: {reason}");
+                let thumbnail = get_thumbnail_path(&path)?;
+                if thumbnail.exists() {
+                    fs::remove_file(&thumbnail)
+                        .await
+                        .with_context(|| "Failed to delete thumbnail of damaged image")?;
+                }
+                if let Some(source) = sources.iter().find(|s| s.name == filename) {
+                    let cache_path = path.to_string_lossy().to_string();
+                    let result = self
+                        .download_file(
+                            &res.client,
+                            &source.url,
+                            &cache_path,
+                            &filename,
+                            res.total_steps as u8,
+                            res.notification.clone(),
+                        )
+                        .await;
+                    if let Ok(size) = check_err(result) {
+                        res.downloaded += size;
+                        repaired += 1;
+                        continue;
+                    }
+                }
+                // the source is unknown (or unavailable); the next refresh will fetch it again
+                fs::remove_file(&path)
+                    .await
+                    .with_context(|| "Failed to delete damaged cache file")?;
+                removed += 1;
+            }
+        }
+
+        // finish up
+        res.notification.percent = 1.0;
+        res.notification.status = NotificationStatus {
+            downloaded: (res.downloaded > 0).then(|| {
+                Size::from_bytes(res.downloaded)
+                    .format()
+                    .with_base(size::Base::Base10)
+                    .to_string()
+            }),
+            removed: Some(removed.try_into().unwrap_or(u32::MAX)),
+            elapsed: Some(condense_duration(timer.elapsed())),
+        };
+        res.notification.body = if repaired == 0 && removed == 0 {
+            format!("Checked {checked} images; no problems found")
+        } else {
+            format!("Checked {checked} images; repaired {repaired}, removed {removed} files")
+        };
+        self.check_notify_send_error(res.notification).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_image;
+    use crate::common::testing::TempDir;
+    use std::fs;
+
+    #[tokio::test]
+    async fn detects_truncated_images() {
+        let dir = TempDir::new("verify");
+        let image = dir.join("2025-01-01.png");
+        image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8, y as u8, 0]))
+            .save(&image)
+            .unwrap();
+        let complete = check_image(&image).await;
+        let bytes = fs::read(&image).unwrap();
+        fs::write(&image, &bytes[..bytes.len() / 2]).unwrap();
+        let truncated = check_image(&image).await;
+        fs::write(&image, b"").unwrap();
+        let empty = check_image(&image).await;
+
+        assert_eq!(complete.unwrap(), None);
+        assert!(truncated.unwrap().is_some());
+        assert_eq!(empty.unwrap().as_deref(), Some("file is empty"));
+    }
+}
@@ -6,21 +6,66 @@
 //! instead, share memory by communicating.
 #![cfg(not(target_os = "android"))]
 
+mod history;
+pub(crate) use history::{HistoryState, HISTORY_FILE};
+pub use history::{ImportHistory, Reverted};
+
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use crate::control::{self, ControlRequest};
 use crate::{
+    backends::{create_backend, WallpaperBackend},
+    caption::{get_caption_key, render_caption},
     common::check_err,
+    monitors::list_monitors,
     notification_center::{NotificationActor, NotificationUpdate},
+    processing::{pick_auto_mode, process_image, prune_applied, touch, AutoMode, APPLIED_DIR},
+    services::{find_image_description, get_app_cache_dir, settings::Settings, TEMP_FILE_EXT},
+    settings_center::{edit_settings, get_settings, SettingsActor},
     signals::{
-        NotificationAlert, NotificationSeverity, NotificationStatus, SetWallpaper, WallpaperMode,
+        Caption, CropStrategy, ImageProcessing, ListMonitors, Monitor, MonitorList,
+        NotificationAlert, NotificationSeverity, NotificationStatus, SetWallpaper,
+        WallpaperBackendConfig, WallpaperHistoryEntry, WallpaperMode, WallpaperSelection,
     },
 };
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
 use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler};
-use rinf::{debug_print, DartSignal};
-use tokio::spawn;
-use wallpaper::{DesktopClient, DesktopWallpaper};
+use rinf::{debug_print, DartSignal, RustSignal};
+use tokio::{fs, spawn, task::spawn_blocking};
+
+/// The name of the folder (in the app's cache folder) that holds copies of the active wallpapers.
+///
+/// The desktop is pointed at these copies instead of the cached images,
+/// so the wallpaper survives the cache being pruned.
+const ACTIVE_DIR: &str = "active";
+
+/// Is the file at `path` a dynamic background (XML) that refers to other images?
+fn is_dynamic_background(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("xml"))
+}
+
+/// Set the wallpapers saved for each monitor (see [`Settings::monitors`]) again.
+///
+/// Desktops may forget a wallpaper that was set for a single monitor (e.g. when they restart),
+/// so this is sent when the wallpaper actors start.
+pub struct RestoreMonitorWallpapers;
 
 // The actor that holds the counter state and handles messages.
-pub struct WallpaperActor;
+pub struct WallpaperActor {
+    app_cache_dir: PathBuf,
+    /// The recent wallpaper changes (newest first).
+    history: Vec<WallpaperHistoryEntry>,
+    settings: Address<SettingsActor>,
+    /// The backend that set the wallpaper last, and the config it was created from.
+    backend: Option<(WallpaperBackendConfig, Box<dyn WallpaperBackend>)>,
+    /// The control socket of a running daemon, which sets the wallpaper
+    /// (and records the history) instead.
+    daemon: Option<PathBuf>,
+}
 
 // Implementing the `Actor` trait for `CountingActor`.
 // This defines `CountingActor` as an actor in the async system.
@@ -29,13 +74,350 @@ impl Actor for WallpaperActor {}
 impl WallpaperActor {
     pub fn new(
         setter_addr: Address<Self>,
+        app_cache_dir: PathBuf,
+        history: Vec<WallpaperHistoryEntry>,
         notification_center: Address<NotificationActor>,
+        settings: Address<SettingsActor>,
+        daemon: Option<PathBuf>,
     ) -> Self {
+        spawn(Self::listen_to_list_monitors(setter_addr.clone()));
+        spawn(Self::listen_to_history_refresh(setter_addr.clone()));
+        spawn(Self::listen_to_revert(
+            setter_addr.clone(),
+            notification_center.clone(),
+        ));
         spawn(Self::listen_to_set_wallpaper_trigger(
             setter_addr,
             notification_center,
         ));
-        Self {}
+        Self {
+            app_cache_dir,
+            history,
+            settings,
+            backend: None,
+            daemon,
+        }
+    }
+
+    async fn listen_to_list_monitors(mut setter_addr: Address<Self>) {
+        let receiver = ListMonitors::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            let monitors = setter_addr
+                .send(dart_signal.message)
+                .await
+                .map_err(|e| anyhow!("Failed to list monitors: {e:?}"));
+            MonitorList {
+                monitors: check_err(monitors).and_then(check_err).unwrap_or_default(),
+            }
+            .send_signal_to_dart();
+        }
+    }
+
+    /// Get the size (in pixels) to fit images to.
+    ///
+    /// Returns `None` if the size is not configured and the `monitor`'s resolution is unknown.
+    /// If no `monitor` is specified, then the primary monitor's resolution is used.
+    async fn get_target_size(
+        processing: &ImageProcessing,
+        monitor: Option<&str>,
+    ) -> Option<(u32, u32)> {
+        if processing.width > 0 && processing.height > 0 {
+            return Some((processing.width, processing.height));
+        }
+        let monitors = check_err(list_monitors().await).ok()?;
+        let target = match monitor {
+            Some(name) => monitors.iter().find(|m| m.name == name),
+            None => monitors.iter().find(|m| m.primary).or(monitors.first()),
+        }?;
+        (target.width > 0 && target.height > 0).then_some((target.width, target.height))
+    }
+
+    /// Get a name for a file derived from the cached image at `src`.
+    ///
+    /// Services name their images similarly, so this includes the service's folder name.
+    fn get_derived_name(src: &Path) -> Result<String> {
+        let folder = src
+            .parent()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let stem = src
+            .file_stem()
+            .ok_or(anyhow!("Failed to get file name of {src:?}"))?
+            .to_string_lossy();
+        Ok(format!("{folder}-{stem}"))
+    }
+
+    async fn ensure_applied_dir(&self) -> Result<PathBuf> {
+        let applied_dir = self.app_cache_dir.join(APPLIED_DIR);
+        if !applied_dir.exists() {
+            fs::create_dir_all(&applied_dir)
+                .await
+                .with_context(|| "Failed to create processed images folder")?;
+        }
+        Ok(applied_dir)
+    }
+
+    /// Replace [`WallpaperMode::Auto`] in the `selection` with the mode picked for its image.
+    ///
+    /// Returns the picked mode, or `None` if the `selection` did not use the automatic mode.
+    async fn resolve_auto_mode(
+        selection: &mut WallpaperSelection,
+        processing: &ImageProcessing,
+    ) -> Result<Option<AutoMode>> {
+        if !matches!(selection.mode, Some(WallpaperMode::Auto)) {
+            return Ok(None);
+        }
+        if is_dynamic_background(&selection.path) {
+            // the images of a dynamic background are all shown the same way
+            selection.mode = Some(WallpaperMode::Crop);
+            return Ok(None);
+        }
+        let size = image::image_dimensions(&selection.path)
+            .with_context(|| format!("Failed to get size of image {}", selection.path))?;
+        let screen = Self::get_target_size(processing, selection.monitor.as_deref()).await;
+        let auto = pick_auto_mode(size, screen);
+        debug_print!("Automatic wallpaper mode {auto}");
+        selection.mode = Some(auto.mode);
+        Ok(Some(auto))
+    }
+
+    /// Get the path of the image to set as the wallpaper for the given `selection`.
+    ///
+    /// Depending on the `settings`, the selected image is fitted to the screen
+    /// and its description is rendered onto it. Otherwise, this is the selected image's path.
+    async fn prepare_image(
+        &self,
+        selection: &WallpaperSelection,
+        settings: &Settings,
+    ) -> Result<String> {
+        if is_dynamic_background(&selection.path) {
+            return Ok(selection.path.clone());
+        }
+        let path = self.fit_to_screen(selection, &settings.processing).await?;
+        if !settings.caption.enabled {
+            return Ok(path);
+        }
+        // a missing caption should not prevent changing the wallpaper
+        Ok(check_err(
+            self.add_caption(
+                Path::new(&selection.path),
+                Path::new(&path),
+                &settings.caption,
+            )
+            .await,
+        )
+        .ok()
+        .flatten()
+        .unwrap_or(path))
+    }
+
+    /// Render the description of the cached image at `src` onto the `image`.
+    ///
+    /// Returns the path to the captioned image,
+    /// or `None` if there is no description for the cached image.
+    /// A caption rendered earlier with the same description and options is reused.
+    async fn add_caption(
+        &self,
+        src: &Path,
+        image: &Path,
+        caption: &Caption,
+    ) -> Result<Option<String>> {
+        let Some(description) = find_image_description(src).await? else {
+            debug_print!("Skipping caption because {src:?} has no description");
+            return Ok(None);
+        };
+        let applied_dir = self.ensure_applied_dir().await?;
+        let name = if image.parent() == Some(applied_dir.as_path()) {
+            image
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        } else {
+            Self::get_derived_name(image)?
+        };
+        let key = get_caption_key(&description, caption);
+        let dest = applied_dir.join(format!("{name}-caption-{key}.jpg"));
+        if dest.exists() {
+            touch(&dest)?;
+            return Ok(Some(dest.to_string_lossy().to_string()));
+        }
+        debug_print!("Rendering caption onto {image:?}");
+        let (src, output, caption) = (image.to_path_buf(), dest.clone(), caption.clone());
+        spawn_blocking(move || render_caption(&src, &output, &description, &caption))
+            .await
+            .map_err(|e| anyhow!("Failed to join caption rendering task: {e:?}"))??;
+        check_err(prune_applied(&applied_dir).await)?;
+        Ok(Some(dest.to_string_lossy().to_string()))
+    }
+
+    /// Get the path of the `selection`'s image fitted to the screen.
+    ///
+    /// If image `processing` is disabled (or not applicable), this is the selected image's path.
+    async fn fit_to_screen(
+        &self,
+        selection: &WallpaperSelection,
+        processing: &ImageProcessing,
+    ) -> Result<String> {
+        let fit = match selection.mode {
+            Some(WallpaperMode::Crop) => false,
+            Some(WallpaperMode::Fit) => true,
+            _ => return Ok(selection.path.clone()),
+        };
+        if !processing.enabled {
+            return Ok(selection.path.clone());
+        }
+        let Some((width, height)) =
+            Self::get_target_size(processing, selection.monitor.as_deref()).await
+        else {
+            debug_print!("Skipping image processing because the screen size is unknown");
+            return Ok(selection.path.clone());
+        };
+        let src = PathBuf::from(&selection.path);
+        let name = Self::get_derived_name(&src)?;
+        let method = match (fit, processing.crop) {
+            (true, _) => "fit",
+            (false, CropStrategy::Center) => "center",
+            (false, CropStrategy::Saliency) => "saliency",
+        };
+        let applied_dir = self.ensure_applied_dir().await?;
+        let dest = applied_dir.join(format!("{name}-{width}x{height}-{method}.jpg"));
+        if dest.exists() {
+            touch(&dest)?;
+        } else {
+            debug_print!("Processing {src:?} to fit {width}x{height}");
+            let crop = processing.crop;
+            let output = dest.clone();
+            spawn_blocking(move || process_image(&src, &output, width, height, fit, crop))
+                .await
+                .map_err(|e| anyhow!("Failed to join image processing task: {e:?}"))??;
+            check_err(prune_applied(&applied_dir).await)?;
+        }
+        Ok(dest.to_string_lossy().to_string())
+    }
+
+    /// Get the prefix of the active copy's file name for a `monitor` (or all monitors).
+    fn get_active_prefix(monitor: Option<&str>) -> String {
+        let target = monitor.map_or("all".to_string(), |name| {
+            name.chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect()
+        });
+        format!("{target}--")
+    }
+
+    /// Copy the image at `path` to the [`ACTIVE_DIR`], so it can be set as the wallpaper.
+    ///
+    /// Every copy gets a new name (even for the same image), because some desktops ignore
+    /// a change to the same path, e.g. after a caption was rendered again with other options.
+    /// Old copies are removed by [`Self::remove_inactive_copies()`].
+    async fn copy_to_active(&self, path: &str, monitor: Option<&str>) -> Result<PathBuf> {
+        let active_dir = self.app_cache_dir.join(ACTIVE_DIR);
+        if !active_dir.exists() {
+            fs::create_dir_all(&active_dir)
+                .await
+                .with_context(|| "Failed to create active wallpapers folder")?;
+        }
+        let src = Path::new(path);
+        let name = if src.parent() == Some(self.app_cache_dir.join(APPLIED_DIR).as_path()) {
+            src.file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        } else {
+            Self::get_derived_name(src)?
+        };
+        let ext = src
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        let prefix = Self::get_active_prefix(monitor);
+        let copied_at = Utc::now().timestamp_millis();
+        let dest = active_dir.join(format!("{prefix}{name}-{copied_at}{ext}"));
+        // copy to a temp file first, so an interrupted copy never leaves a partial image
+        let mut temp_path = dest.as_os_str().to_owned();
+        temp_path.push(format!(".{TEMP_FILE_EXT}"));
+        fs::copy(src, &temp_path)
+            .await
+            .with_context(|| format!("Failed to copy {src:?} to active wallpapers folder"))?;
+        fs::rename(&temp_path, &dest)
+            .await
+            .with_context(|| format!("Failed to move active wallpaper to {dest:?}"))?;
+        Ok(dest)
+    }
+
+    /// Remove the copies in the [`ACTIVE_DIR`] that are no longer shown,
+    /// after the `active` copy was set as the wallpaper of a `monitor` (or all monitors).
+    async fn remove_inactive_copies(&self, active: &Path, monitor: Option<&str>) -> Result<()> {
+        // a wallpaper for all monitors replaces the wallpaper of every monitor
+        let prefix = monitor.map(|monitor| Self::get_active_prefix(Some(monitor)));
+        let mut entries = fs::read_dir(self.app_cache_dir.join(ACTIVE_DIR))
+            .await
+            .with_context(|| "Failed to read active wallpapers folder contents.")?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .with_context(|| "Failed to traverse active wallpapers folder")?
+        {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if path == active || prefix.as_ref().is_some_and(|p| !name.starts_with(p)) {
+                continue;
+            }
+            fs::remove_file(&path)
+                .await
+                .with_context(|| format!("Failed to remove inactive wallpaper {path:?}"))?;
+        }
+        Ok(())
+    }
+
+    /// Get the backend for the `config`.
+    ///
+    /// The backend is kept while the `config` stays the same,
+    /// because a wallpaper command may keep running until the next wallpaper is set.
+    async fn get_backend(&mut self, config: &WallpaperBackendConfig) -> &mut dyn WallpaperBackend {
+        if self
+            .backend
+            .as_ref()
+            .is_some_and(|(current, _)| current != config)
+        {
+            if let Some((_, mut previous)) = self.backend.take() {
+                previous.stop().await;
+            }
+        }
+        let (_, backend) = self
+            .backend
+            .get_or_insert_with(|| (config.clone(), create_backend(config)));
+        backend.as_mut()
+    }
+
+    /// Set the wallpaper to the `selection`, without recording it in the history.
+    ///
+    /// Returns the mode picked for [`WallpaperMode::Auto`] (if used).
+    async fn apply(&mut self, selection: &WallpaperSelection) -> Result<Option<AutoMode>> {
+        let settings = get_settings(&mut self.settings).await?;
+        let mut selection = selection.clone();
+        let auto = Self::resolve_auto_mode(&mut selection, &settings.processing).await?;
+        let path = self.prepare_image(&selection, &settings).await?;
+        let monitor = selection.monitor.as_deref();
+        let active = self.copy_to_active(&path, monitor).await?;
+        self.get_backend(&settings.backend)
+            .await
+            .set_wallpaper(&active.to_string_lossy(), selection.mode, monitor)
+            .await?;
+        // the previous wallpaper is only removed once it is replaced
+        let _ = check_err(self.remove_inactive_copies(&active, monitor).await);
+        edit_settings(&mut self.settings, move |settings| {
+            match selection.monitor {
+                Some(monitor) => {
+                    settings.monitors.insert(monitor, selection.path);
+                }
+                // all monitors show the same image now
+                None => settings.monitors.clear(),
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(auto)
     }
 
     async fn listen_to_set_wallpaper_trigger(
@@ -51,6 +433,7 @@ impl WallpaperActor {
             // Send a message to the actor.
             let notification = match setter_addr.send(dart_signal.message).await {
                 Err(e) => NotificationAlert {
+                    id: NotificationAlert::next_id(),
                     title: "Failed to set the desktop wallpaper".to_string(),
                     body: format!("{e:?}"),
                     percent: 1.0,
@@ -58,14 +441,19 @@ impl WallpaperActor {
                     status: NotificationStatus::default(),
                 },
                 Ok(result) => match result {
-                    Ok(_) => NotificationAlert {
+                    Ok(auto) => NotificationAlert {
+                        id: NotificationAlert::next_id(),
                         title: "Set the desktop wallpaper".to_string(),
-                        body: "Success".to_string(),
+                        body: match auto {
+                            Some(auto) => format!("Success ({auto})"),
+                            None => "Success".to_string(),
+                        },
                         percent: 1.0,
                         severity: NotificationSeverity::Info,
                         status: NotificationStatus::default(),
                     },
                     Err(e) => NotificationAlert {
+                        id: NotificationAlert::next_id(),
                         title: "Failed to set the desktop wallpaper".to_string(),
                         body: format!("{e:?}"),
                         percent: 1.0,
@@ -88,40 +476,123 @@ impl WallpaperActor {
 
 #[async_trait]
 impl Handler<SetWallpaper> for WallpaperActor {
-    type Result = Result<()>;
+    /// The mode picked for [`WallpaperMode::Auto`] (if used).
+    type Result = Result<Option<AutoMode>>;
     // Handles messages received by the actor.
     async fn handle(&mut self, msg: SetWallpaper, _context: &MsgContext<Self>) -> Self::Result {
         let selection = msg.selected;
         debug_print!(
-            "Setting wallpaper to {} (mode: {:?})",
+            "Setting wallpaper to {} (mode: {:?}, monitor: {:?})",
             selection.path,
-            selection.mode
+            selection.mode,
+            selection.monitor
         );
-        let mode = selection.mode.map(|mode| match mode {
-            WallpaperMode::Center => wallpaper::Mode::Center,
-            WallpaperMode::Crop => wallpaper::Mode::Crop,
-            WallpaperMode::Fit => wallpaper::Mode::Fit,
-            WallpaperMode::Stretch => wallpaper::Mode::Stretch,
-            WallpaperMode::Tile => wallpaper::Mode::Tile,
-        });
-        let mut client = check_err(
-            DesktopWallpaper::new()
-                .map_err(|e| anyhow!("Failed to init desktop wallpaper client: {e:?}")),
-        )?;
-        client
-            .set_wallpaper(&selection.path, mode)
-            .map_err(|e| anyhow!("Failed to set wallpaper {}: {e:?}", selection.path))
+        #[cfg(unix)]
+        if let Some(socket_path) = &self.daemon {
+            control::forward(
+                socket_path,
+                ControlRequest::Set {
+                    path: selection.path,
+                    mode: selection.mode,
+                    monitor: selection.monitor,
+                },
+            )
+            .await?;
+            self.history = history::load_history(&self.app_cache_dir).await;
+            return Ok(None);
+        }
+        let auto = self.apply(&selection).await?;
+        self.record_history(&selection).await;
+        Ok(auto)
     }
 }
 
+#[async_trait]
+impl Handler<ListMonitors> for WallpaperActor {
+    type Result = Result<Vec<Monitor>>;
+
+    async fn handle(&mut self, _msg: ListMonitors, _context: &MsgContext<Self>) -> Self::Result {
+        let settings = get_settings(&mut self.settings).await?;
+        let mut monitors = list_monitors().await?;
+        for monitor in &mut monitors {
+            monitor.wallpaper = settings.monitors.get(&monitor.name).cloned();
+        }
+        Ok(monitors)
+    }
+}
+
+#[async_trait]
+impl Handler<RestoreMonitorWallpapers> for WallpaperActor {
+    type Result = Result<()>;
+
+    async fn handle(
+        &mut self,
+        _msg: RestoreMonitorWallpapers,
+        _context: &MsgContext<Self>,
+    ) -> Self::Result {
+        let settings = get_settings(&mut self.settings).await?;
+        if settings.monitors.is_empty() {
+            return Ok(());
+        }
+        let connected = list_monitors().await?;
+        for (monitor, path) in settings.monitors {
+            // keep the wallpaper of a disconnected monitor for when it is connected again
+            if !connected.iter().any(|m| m.name == monitor) || !Path::new(&path).exists() {
+                continue;
+            }
+            // the mode is only recorded in the history
+            let mode = self
+                .history
+                .iter()
+                .find(|entry| entry.monitor.as_ref() == Some(&monitor))
+                .and_then(|entry| entry.mode);
+            debug_print!("Restoring the wallpaper of monitor {monitor}");
+            let selection = WallpaperSelection {
+                path,
+                mode,
+                monitor: Some(monitor),
+            };
+            let _ = check_err(self.apply(&selection).await);
+        }
+        Ok(())
+    }
+}
+
+/// Send [`RestoreMonitorWallpapers`] to the `wallpaper_addr` in the background.
+pub(crate) fn restore_monitor_wallpapers(mut wallpaper_addr: Address<WallpaperActor>) {
+    spawn(async move {
+        let result = wallpaper_addr
+            .send(RestoreMonitorWallpapers)
+            .await
+            .map_err(|e| anyhow!("Failed to restore monitor wallpapers: {e:?}"));
+        if let Ok(result) = check_err(result) {
+            let _ = check_err(result);
+        }
+    });
+}
+
 // Creates and spawns the actors in the async system.
-pub async fn create_actors(notification_center: Address<NotificationActor>) -> Result<()> {
+// If a `daemon` is listening on the given control socket, then it sets the wallpaper instead.
+pub async fn create_actors(
+    notification_center: Address<NotificationActor>,
+    settings: Address<SettingsActor>,
+    daemon: Option<PathBuf>,
+) -> Result<Address<WallpaperActor>> {
     // Create actor contexts.
     let wallpaper_context = MsgContext::new();
     let wall_addr = wallpaper_context.address();
 
     // Spawn actors.
-    let actor = WallpaperActor::new(wall_addr, notification_center);
+    let app_cache_dir = get_app_cache_dir()?;
+    let history = history::load_history(&app_cache_dir).await;
+    let actor = WallpaperActor::new(
+        wall_addr.clone(),
+        app_cache_dir,
+        history,
+        notification_center,
+        settings,
+        daemon,
+    );
     spawn(wallpaper_context.run(actor));
-    Ok(())
+    Ok(wall_addr)
 }
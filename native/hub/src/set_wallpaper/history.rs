@@ -0,0 +1,389 @@
+//! Records every wallpaper change, so users can find an earlier wallpaper again
+//! or undo a change they did not like.
+
+use std::path::Path;
+
+use super::WallpaperActor;
+#[cfg(unix)]
+use crate::control::{self, ControlRequest};
+use crate::{
+    common::check_err,
+    notification_center::{NotificationActor, NotificationUpdate},
+    services::{find_image_description, find_image_service},
+    signals::{
+        NotificationAlert, NotificationSeverity, NotificationStatus, RevertWallpaper,
+        WallpaperHistory, WallpaperHistoryEntry, WallpaperHistoryRefresh, WallpaperSelection,
+    },
+};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+use messages::prelude::{async_trait, Address, Context as MsgContext, Handler};
+use rinf::{DartSignal, RustSignal};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// The name of the file (in the app's cache folder) that records the wallpaper history.
+pub(crate) const HISTORY_FILE: &str = "history.json";
+
+/// The number of wallpaper changes that are remembered.
+const MAX_HISTORY: usize = 100;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct HistoryState {
+    /// Newest first.
+    pub entries: Vec<WallpaperHistoryEntry>,
+}
+
+/// Merge wallpaper changes (e.g. recorded on another machine) into the history.
+pub struct ImportHistory {
+    pub entries: Vec<WallpaperHistoryEntry>,
+}
+
+/// The outcome of a [`RevertWallpaper`].
+pub struct Reverted {
+    /// The entry of the restored wallpaper.
+    pub entry: WallpaperHistoryEntry,
+    /// The images of more recent entries that were skipped, because they no longer exist.
+    pub missing: Vec<String>,
+}
+
+/// Read the wallpaper history cached in `app_cache_dir` (newest first).
+pub(super) async fn load_history(app_cache_dir: &Path) -> Vec<WallpaperHistoryEntry> {
+    let path = app_cache_dir.join(HISTORY_FILE);
+    if !path.exists() {
+        return vec![];
+    }
+    check_err(
+        fs::read_to_string(path)
+            .await
+            .with_context(|| "Failed to read wallpaper history")
+            .and_then(|text| {
+                serde_json::from_str::<HistoryState>(&text)
+                    .with_context(|| "Failed to deserialize wallpaper history")
+            }),
+    )
+    .unwrap_or_default()
+    .entries
+}
+
+/// Add the `entries` that are not in the `history` yet (keeping it newest first).
+///
+/// An entry is known if it was set at the same time to the same image on the same monitor.
+/// Returns the number of entries that were added.
+fn merge_history(
+    history: &mut Vec<WallpaperHistoryEntry>,
+    entries: Vec<WallpaperHistoryEntry>,
+) -> usize {
+    let count = history.len();
+    for entry in entries {
+        let is_known = history.iter().any(|known| {
+            known.set_at == entry.set_at
+                && known.path == entry.path
+                && known.monitor == entry.monitor
+        });
+        if !is_known {
+            history.push(entry);
+        }
+    }
+    let added = history.len() - count;
+    if added > 0 {
+        // entries with an invalid time are the oldest
+        history.sort_by_cached_key(|entry| {
+            std::cmp::Reverse(DateTime::parse_from_rfc3339(&entry.set_at).ok())
+        });
+        history.truncate(MAX_HISTORY);
+    }
+    added
+}
+
+/// Find the entries to revert the wallpaper of a `monitor` (or all monitors) from and to.
+///
+/// Returns the index of the current entry, the index of the entry to revert to,
+/// and the images of the entries in between that were skipped because they no longer exist.
+fn find_revert_target(
+    history: &[WallpaperHistoryEntry],
+    monitor: Option<&str>,
+) -> Result<(usize, usize, Vec<String>)> {
+    let mut matching = history
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.monitor.as_deref() == monitor);
+    let Some((current, _)) = matching.next() else {
+        bail!("There is no earlier wallpaper to revert to");
+    };
+    let mut missing = vec![];
+    for (index, entry) in matching {
+        if Path::new(&entry.path).exists() {
+            return Ok((current, index, missing));
+        }
+        missing.push(entry.path.clone());
+    }
+    if missing.is_empty() {
+        bail!("There is no earlier wallpaper to revert to");
+    }
+    bail!(
+        "None of the earlier wallpapers exist anymore: {}",
+        missing.join(", ")
+    );
+}
+
+impl WallpaperActor {
+    pub(super) async fn listen_to_history_refresh(mut setter_addr: Address<Self>) {
+        let receiver = WallpaperHistoryRefresh::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            if let Ok(entries) = setter_addr.send(dart_signal.message).await {
+                WallpaperHistory { entries }.send_signal_to_dart();
+            }
+        }
+    }
+
+    pub(super) async fn listen_to_revert(
+        mut setter_addr: Address<Self>,
+        mut notification_center: Address<NotificationActor>,
+    ) {
+        let receiver = RevertWallpaper::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            let result = setter_addr
+                .send(dart_signal.message)
+                .await
+                .map_err(|e| anyhow!("Failed to send revert request: {e:?}"))
+                .and_then(|result| result);
+            let notification = match result {
+                Ok(Reverted { entry, missing }) if missing.is_empty() => NotificationAlert {
+                    id: NotificationAlert::next_id(),
+                    title: "Reverted the desktop wallpaper".to_string(),
+                    body: entry.path,
+                    percent: 1.0,
+                    severity: NotificationSeverity::Info,
+                    status: NotificationStatus::default(),
+                },
+                Ok(Reverted { entry, missing }) => NotificationAlert {
+                    id: NotificationAlert::next_id(),
+                    title: "Reverted the desktop wallpaper".to_string(),
+                    body: format!(
+                        "{}\nSkipped images that no longer exist: {}",
+                        entry.path,
+                        missing.join(", ")
+                    ),
+                    percent: 1.0,
+                    severity: NotificationSeverity::Warning,
+                    status: NotificationStatus::default(),
+                },
+                Err(e) => NotificationAlert {
+                    id: NotificationAlert::next_id(),
+                    title: "Failed to revert the desktop wallpaper".to_string(),
+                    body: format!("{e:?}"),
+                    percent: 1.0,
+                    severity: NotificationSeverity::Error,
+                    status: NotificationStatus::default(),
+                },
+            };
+            if let Ok(result) = check_err(
+                notification_center
+                    .send(NotificationUpdate(notification))
+                    .await
+                    .map_err(|e| anyhow!("Failed to send notification: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+        }
+    }
+
+    /// Record that the wallpaper was successfully set to the `selection`.
+    pub(super) async fn record_history(&mut self, selection: &WallpaperSelection) {
+        let path = Path::new(&selection.path);
+        let description = check_err(find_image_description(path).await).ok().flatten();
+        self.history.insert(
+            0,
+            WallpaperHistoryEntry {
+                set_at: Local::now().to_rfc3339(),
+                service: find_image_service(path),
+                path: selection.path.clone(),
+                mode: selection.mode,
+                monitor: selection.monitor.clone(),
+                description,
+            },
+        );
+        self.history.truncate(MAX_HISTORY);
+        let _ = check_err(self.save_history().await);
+    }
+
+    async fn save_history(&self) -> Result<()> {
+        let state = HistoryState {
+            entries: self.history.clone(),
+        };
+        fs::write(
+            self.app_cache_dir.join(HISTORY_FILE),
+            serde_json::to_string(&state)
+                .with_context(|| "Failed to convert wallpaper history to JSON")?,
+        )
+        .await
+        .with_context(|| "Failed to write wallpaper history")
+    }
+}
+
+#[async_trait]
+impl Handler<WallpaperHistoryRefresh> for WallpaperActor {
+    type Result = Vec<WallpaperHistoryEntry>;
+
+    async fn handle(
+        &mut self,
+        _msg: WallpaperHistoryRefresh,
+        _context: &MsgContext<Self>,
+    ) -> Self::Result {
+        if self.daemon.is_some() {
+            // the daemon may have changed the wallpaper since
+            self.history = load_history(&self.app_cache_dir).await;
+        }
+        self.history.clone()
+    }
+}
+
+#[async_trait]
+impl Handler<ImportHistory> for WallpaperActor {
+    /// The number of entries that were added.
+    type Result = Result<usize>;
+
+    async fn handle(&mut self, msg: ImportHistory, _context: &MsgContext<Self>) -> Self::Result {
+        let added = merge_history(&mut self.history, msg.entries);
+        if added > 0 {
+            self.save_history().await?;
+        }
+        Ok(added)
+    }
+}
+
+#[async_trait]
+impl Handler<RevertWallpaper> for WallpaperActor {
+    type Result = Result<Reverted>;
+
+    async fn handle(&mut self, msg: RevertWallpaper, _context: &MsgContext<Self>) -> Self::Result {
+        #[cfg(unix)]
+        if let Some(socket_path) = &self.daemon {
+            let request = ControlRequest::Revert {
+                monitor: msg.monitor.clone(),
+            };
+            control::forward(socket_path, request).await?;
+            self.history = load_history(&self.app_cache_dir).await;
+            // the restored wallpaper is the current one
+            // (the daemon reports skipped images itself)
+            let entry = self
+                .history
+                .iter()
+                .find(|entry| entry.monitor == msg.monitor)
+                .cloned()
+                .ok_or(anyhow!(
+                    "The background service did not record the reverted wallpaper"
+                ))?;
+            return Ok(Reverted {
+                entry,
+                missing: vec![],
+            });
+        }
+        let (current, previous, missing) =
+            find_revert_target(&self.history, msg.monitor.as_deref())?;
+        let entry = self.history[previous].clone();
+        self.apply(&WallpaperSelection {
+            path: entry.path.clone(),
+            mode: entry.mode,
+            monitor: entry.monitor.clone(),
+        })
+        .await?;
+        // the restored wallpaper is the current one again
+        self.history.remove(current);
+        check_err(self.save_history().await)?;
+        Ok(Reverted { entry, missing })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_revert_target, merge_history};
+    use crate::{common::testing::TempDir, signals::WallpaperHistoryEntry};
+    use std::fs;
+
+    fn entry(set_at: &str, path: &str, monitor: Option<&str>) -> WallpaperHistoryEntry {
+        WallpaperHistoryEntry {
+            set_at: set_at.to_string(),
+            service: None,
+            path: path.to_string(),
+            mode: None,
+            monitor: monitor.map(str::to_string),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn reverts_to_earlier_wallpaper_of_same_monitor() {
+        let dir = TempDir::new("history");
+        let image = |name: &str| {
+            let path = dir.join(name);
+            fs::write(&path, b"").unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let (a, b, c) = (image("a.jpg"), image("b.jpg"), image("c.jpg"));
+        let history = vec![
+            entry("2025-01-04T00:00:00+00:00", &c, Some("DP-1")),
+            entry("2025-01-03T00:00:00+00:00", &b, None),
+            entry("2025-01-02T00:00:00+00:00", &b, Some("DP-1")),
+            entry("2025-01-01T00:00:00+00:00", &a, None),
+        ];
+        // each monitor has its own history, separate from the wallpaper for all monitors
+        assert_eq!(
+            find_revert_target(&history, Some("DP-1")).unwrap(),
+            (0, 2, vec![])
+        );
+        assert_eq!(find_revert_target(&history, None).unwrap(), (1, 3, vec![]));
+        assert!(find_revert_target(&history, Some("HDMI-1")).is_err());
+        assert!(find_revert_target(&history[2..], Some("DP-1")).is_err());
+        // after reverting, the current entry is popped, so the next revert goes further back
+        let mut popped = history.clone();
+        popped.remove(0);
+        assert!(find_revert_target(&popped, Some("DP-1")).is_err());
+        assert_eq!(find_revert_target(&popped, None).unwrap(), (0, 2, vec![]));
+
+        // images that no longer exist are skipped and reported
+        fs::remove_file(&b).unwrap();
+        let mut history = history;
+        history.push(entry("2024-12-31T00:00:00+00:00", &a, Some("DP-1")));
+        assert_eq!(
+            find_revert_target(&history, Some("DP-1")).unwrap(),
+            (0, 4, vec![b.clone()])
+        );
+        fs::remove_file(&a).unwrap();
+        let error = find_revert_target(&history, Some("DP-1")).unwrap_err();
+        assert!(error.to_string().contains(&a));
+    }
+
+    #[test]
+    fn imports_only_unknown_entries() {
+        let mut history = vec![
+            entry("2025-01-03T00:00:00+00:00", "c.jpg", None),
+            entry("2025-01-01T00:00:00+00:00", "a.jpg", None),
+        ];
+        let imported = vec![
+            // known
+            entry("2025-01-01T00:00:00+00:00", "a.jpg", None),
+            // the same image set at another time, or on another monitor
+            entry("2025-01-02T00:00:00+00:00", "a.jpg", None),
+            entry("2025-01-01T00:00:00+00:00", "a.jpg", Some("DP-1")),
+            entry("not a time", "b.jpg", None),
+        ];
+        assert_eq!(merge_history(&mut history, imported.clone()), 3);
+        assert_eq!(merge_history(&mut history, imported), 0);
+        let order = history
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.set_at.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            [
+                ("c.jpg", "2025-01-03T00:00:00+00:00"),
+                ("a.jpg", "2025-01-02T00:00:00+00:00"),
+                ("a.jpg", "2025-01-01T00:00:00+00:00"),
+                ("a.jpg", "2025-01-01T00:00:00+00:00"),
+                ("b.jpg", "not a time"),
+            ]
+        );
+    }
+}
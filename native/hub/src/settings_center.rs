@@ -0,0 +1,425 @@
+//! The settings center owns the settings: all other actors read and change them through it,
+//! and the actors that depend on the settings are told about changes.
+//!
+//! The settings file is also watched, so that edits made outside the app
+//! (e.g. by hand or by syncing dotfiles) are applied while it runs.
+
+use std::{path::PathBuf, time::SystemTime};
+
+#[cfg(all(unix, not(target_os = "android")))]
+use crate::control::{self, ControlRequest};
+use crate::{
+    common::check_err,
+    notification_center::{NotificationActor, NotificationUpdate},
+    services::settings::{Settings, SettingsChanged, SETTINGS_FILE, SETTINGS_VERSION},
+    signals::{
+        GetSettings, NotificationAlert, NotificationSeverity, NotificationStatus, SettingsState,
+        UpdateSettings, WallpaperModeCache,
+    },
+};
+#[cfg(not(target_os = "android"))]
+use crate::{day_cycle::DayCycleActor, scheduler::SchedulerActor, slideshow::SlideshowActor};
+use anyhow::{anyhow, Result};
+use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler};
+use rinf::{debug_print, DartSignal, RustSignal};
+use tokio::{
+    fs, spawn,
+    time::{interval, Duration, MissedTickBehavior},
+};
+
+/// How often the settings file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Tells the [`SettingsActor`] that the settings file was changed by something else.
+struct ReloadSettings;
+
+/// A function that changes some of the settings.
+type SettingsEdit = Box<dyn FnOnce(&mut Settings) -> Result<()> + Send>;
+
+/// Tells the [`SettingsActor`] to change the current settings with the given function,
+/// and then to save and apply them.
+///
+/// The response is whether the settings changed.
+pub struct EditSettings(pub SettingsEdit);
+
+/// The actors that are told about changed settings.
+///
+/// These are `None` if the actor does not run in this process
+/// (e.g. because a daemon is running instead).
+///
+/// The actors are created after the settings center (so they can read the settings),
+/// so they are sent to it as a message.
+#[derive(Default)]
+pub struct SettingsDependents {
+    #[cfg(not(target_os = "android"))]
+    pub scheduler: Option<Address<SchedulerActor>>,
+    #[cfg(not(target_os = "android"))]
+    pub slideshow: Option<Address<SlideshowActor>>,
+    #[cfg(not(target_os = "android"))]
+    pub day_cycle: Option<Address<DayCycleActor>>,
+}
+
+pub struct SettingsActor {
+    app_cache_dir: PathBuf,
+    notification_center: Address<NotificationActor>,
+    /// The control socket of a running daemon, which saves the settings instead.
+    daemon: Option<PathBuf>,
+    #[cfg_attr(target_os = "android", allow(dead_code))]
+    dependents: SettingsDependents,
+    /// The settings that were applied last.
+    ///
+    /// Invalid edits of the settings file are ignored, so these are kept until it is fixed.
+    current: Settings,
+}
+
+impl Actor for SettingsActor {}
+
+impl SettingsActor {
+    pub fn new(
+        settings_addr: Address<Self>,
+        app_cache_dir: PathBuf,
+        notification_center: Address<NotificationActor>,
+        daemon: Option<PathBuf>,
+        current: Settings,
+    ) -> Self {
+        spawn(Self::listen_to_get_settings(settings_addr.clone()));
+        spawn(Self::listen_to_update_settings(settings_addr.clone()));
+        spawn(Self::listen_to_wallpaper_mode(settings_addr.clone()));
+        spawn(Self::watch_settings_file(
+            settings_addr,
+            app_cache_dir.join(SETTINGS_FILE),
+        ));
+        Self {
+            app_cache_dir,
+            notification_center,
+            daemon,
+            dependents: SettingsDependents::default(),
+            current,
+        }
+    }
+
+    /// Get the time that the file at `path` was last modified (if it exists).
+    async fn get_modified(path: &PathBuf) -> Option<SystemTime> {
+        fs::metadata(path)
+            .await
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// Reload the settings whenever the file at `path` was modified.
+    ///
+    /// The file is only reloaded once it stayed the same for a whole check,
+    /// so that a file that is still being written is not reported as invalid.
+    async fn watch_settings_file(mut settings_addr: Address<Self>, path: PathBuf) {
+        let mut last_modified = Self::get_modified(&path).await;
+        let mut pending = false;
+        let mut timer = interval(WATCH_INTERVAL);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            timer.tick().await;
+            let modified = Self::get_modified(&path).await;
+            if modified != last_modified {
+                last_modified = modified;
+                // a removed file is usually about to be replaced
+                pending = modified.is_some();
+            } else if pending {
+                pending = false;
+                if let Ok(result) = check_err(
+                    settings_addr
+                        .send(ReloadSettings)
+                        .await
+                        .map_err(|e| anyhow!("Failed to reload settings: {e:?}")),
+                ) {
+                    let _ = check_err(result);
+                }
+            }
+        }
+    }
+
+    async fn listen_to_get_settings(mut settings_addr: Address<Self>) {
+        let receiver = GetSettings::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            if let Ok(settings) = check_err(
+                settings_addr
+                    .send(dart_signal.message)
+                    .await
+                    .map_err(|e| anyhow!("Failed to get settings: {e:?}")),
+            ) {
+                SettingsState { settings }.send_signal_to_dart();
+            }
+        }
+    }
+
+    async fn listen_to_update_settings(mut settings_addr: Address<Self>) {
+        let receiver = UpdateSettings::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            if let Ok(result) = check_err(
+                settings_addr
+                    .send(dart_signal.message)
+                    .await
+                    .map_err(|e| anyhow!("Failed to update settings: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+        }
+    }
+
+    async fn listen_to_wallpaper_mode(mut settings_addr: Address<Self>) {
+        let receiver = WallpaperModeCache::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            if let Ok(result) = check_err(
+                settings_addr
+                    .send(dart_signal.message)
+                    .await
+                    .map_err(|e| anyhow!("Failed to update wallpaper mode: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+        }
+    }
+
+    /// Validate and save the `new` settings, and then apply them.
+    ///
+    /// If a daemon is running, then it saves the settings instead.
+    async fn update(&mut self, mut new: Settings) -> Result<()> {
+        new.version = SETTINGS_VERSION;
+        new.services = new.get_services();
+        debug_print!("Updating settings to {new:?}");
+        match &self.daemon {
+            #[cfg(all(unix, not(target_os = "android")))]
+            Some(socket_path) => {
+                let request = ControlRequest::UpdateSettings {
+                    settings: Box::new(new.clone()),
+                };
+                control::forward(socket_path, request).await?;
+            }
+            _ => new.save(&self.app_cache_dir).await?,
+        }
+        self.apply(new).await;
+        Ok(())
+    }
+
+    /// Apply the `new` settings: tell the dependent actors and Dart about them.
+    async fn apply(&mut self, new: Settings) {
+        let old = std::mem::replace(&mut self.current, new.clone());
+        self.notify_dependents(&old, &new).await;
+        if old.mode != new.mode {
+            WallpaperModeCache {
+                mode: Some(new.mode),
+            }
+            .send_signal_to_dart();
+        }
+        SettingsState { settings: new }.send_signal_to_dart();
+    }
+
+    async fn notify(&mut self, notification: NotificationAlert) {
+        if let Ok(result) = check_err(
+            self.notification_center
+                .send(NotificationUpdate(notification))
+                .await
+                .map_err(|e| anyhow!("Failed to send notification: {e:?}")),
+        ) {
+            let _ = check_err(result);
+        }
+    }
+
+    /// Tell the dependent actors (and the notification center) that the settings changed
+    /// from `old` to `new`.
+    ///
+    /// This does not wait for them to handle the change,
+    /// because they may be waiting for the settings themselves.
+    async fn notify_dependents(&mut self, old: &Settings, new: &Settings) {
+        let changed = || SettingsChanged {
+            old: old.clone(),
+            new: new.clone(),
+        };
+        let _ = check_err(
+            self.notification_center
+                .notify(changed())
+                .await
+                .map_err(|e| anyhow!("Failed to update notification settings: {e:?}")),
+        );
+        #[cfg(not(target_os = "android"))]
+        {
+            if let Some(scheduler) = &mut self.dependents.scheduler {
+                let _ = check_err(
+                    scheduler
+                        .notify(changed())
+                        .await
+                        .map_err(|e| anyhow!("Failed to update scheduler settings: {e:?}")),
+                );
+            }
+            if let Some(slideshow) = &mut self.dependents.slideshow {
+                let _ = check_err(
+                    slideshow
+                        .notify(changed())
+                        .await
+                        .map_err(|e| anyhow!("Failed to update slideshow settings: {e:?}")),
+                );
+            }
+            if let Some(day_cycle) = &mut self.dependents.day_cycle {
+                let _ = check_err(
+                    day_cycle
+                        .notify(changed())
+                        .await
+                        .map_err(|e| anyhow!("Failed to update day cycle settings: {e:?}")),
+                );
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Handler<GetSettings> for SettingsActor {
+    type Result = Settings;
+
+    async fn handle(&mut self, _msg: GetSettings, _context: &MsgContext<Self>) -> Self::Result {
+        // while the settings file is invalid, the last good settings are still in use
+        self.current.clone()
+    }
+}
+
+#[async_trait]
+impl Handler<UpdateSettings> for SettingsActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, msg: UpdateSettings, _context: &MsgContext<Self>) -> Self::Result {
+        self.update(msg.settings).await
+    }
+}
+
+#[async_trait]
+impl Handler<EditSettings> for SettingsActor {
+    type Result = Result<bool>;
+
+    async fn handle(&mut self, msg: EditSettings, _context: &MsgContext<Self>) -> Self::Result {
+        let mut new = self.current.clone();
+        (msg.0)(&mut new)?;
+        if new == self.current {
+            return Ok(false);
+        }
+        self.update(new).await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl Handler<SettingsDependents> for SettingsActor {
+    type Result = ();
+
+    async fn handle(
+        &mut self,
+        msg: SettingsDependents,
+        _context: &MsgContext<Self>,
+    ) -> Self::Result {
+        self.dependents = msg;
+    }
+}
+
+#[async_trait]
+impl Handler<WallpaperModeCache> for SettingsActor {
+    type Result = Result<()>;
+
+    /// Get (if `mode` is `None`) or set only the wallpaper mode.
+    async fn handle(
+        &mut self,
+        msg: WallpaperModeCache,
+        _context: &MsgContext<Self>,
+    ) -> Self::Result {
+        match msg.mode.filter(|mode| *mode != self.current.mode) {
+            // applying the changed settings sends the new mode to Dart
+            Some(mode) => {
+                let new = Settings {
+                    mode,
+                    ..self.current.clone()
+                };
+                self.update(new).await?;
+            }
+            None => WallpaperModeCache {
+                mode: Some(self.current.mode),
+            }
+            .send_signal_to_dart(),
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Handler<ReloadSettings> for SettingsActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, _msg: ReloadSettings, _context: &MsgContext<Self>) -> Self::Result {
+        let result = Settings::load(&self.app_cache_dir)
+            .await
+            .and_then(|settings| settings.validate().map(|_| settings));
+        match result {
+            // the file is also written whenever the app changes a setting
+            Ok(settings) if settings == self.current => {}
+            Ok(settings) => {
+                debug_print!("Reloading changed settings");
+                self.apply(settings).await;
+            }
+            Err(e) => {
+                self.notify(NotificationAlert {
+                    id: NotificationAlert::next_id(),
+                    title: "Settings".to_string(),
+                    body: format!("Ignored invalid changes to the settings file: {e:?}"),
+                    percent: 1.0,
+                    severity: NotificationSeverity::Warning,
+                    status: NotificationStatus::default(),
+                })
+                .await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the current settings from the settings center.
+pub(crate) async fn get_settings(settings_addr: &mut Address<SettingsActor>) -> Result<Settings> {
+    settings_addr
+        .send(GetSettings)
+        .await
+        .map_err(|e| anyhow!("Failed to get settings: {e:?}"))
+}
+
+/// Let the settings center change the current settings with `edit`, and save them.
+///
+/// Returns whether the settings changed.
+pub(crate) async fn edit_settings(
+    settings_addr: &mut Address<SettingsActor>,
+    edit: impl FnOnce(&mut Settings) -> Result<()> + Send + 'static,
+) -> Result<bool> {
+    settings_addr
+        .send(EditSettings(Box::new(edit)))
+        .await
+        .map_err(|e| anyhow!("Failed to change settings: {e:?}"))?
+}
+
+// Creates and spawns the actors in the async system.
+//
+// If a `daemon` is listening on the given control socket, then it saves the settings instead.
+// The dependent actors are registered later with a [`SettingsDependents`] message.
+pub async fn create_actors(
+    app_cache_dir: PathBuf,
+    notification_addr: Address<NotificationActor>,
+    daemon: Option<PathBuf>,
+) -> Result<Address<SettingsActor>> {
+    // broken settings should not prevent the app from starting
+    let current = check_err(Settings::load(&app_cache_dir).await).unwrap_or_default();
+
+    // Create actor contexts.
+    let settings_context = MsgContext::new();
+    let settings_addr = settings_context.address();
+
+    // Spawn actors.
+    let actor = SettingsActor::new(
+        settings_addr.clone(),
+        app_cache_dir,
+        notification_addr,
+        daemon,
+        current,
+    );
+    spawn(settings_context.run(actor));
+    Ok(settings_addr)
+}
@@ -0,0 +1,30 @@
+use rinf::{DartSignal, SignalPiece};
+use serde::{Deserialize, Serialize};
+
+/// What to do when an imported file already exists (with different contents).
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
+pub enum ImportConflict {
+    /// Keep the existing file (or setting) and skip the imported one.
+    KeepLocal,
+    /// Replace the existing file (or setting) with the imported one.
+    UseImported,
+}
+
+/// Write the settings and wallpaper history (and optionally the cached images)
+/// into a single archive, so they can be imported on another machine.
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct ExportArchive {
+    /// Where the archive (a tar file) is written.
+    pub path: String,
+    pub include_images: bool,
+}
+
+/// Merge an archive written by [`ExportArchive`] into the app's cache.
+///
+/// The wallpaper history is always merged;
+/// the `conflict` decides about the settings and cached files that already exist.
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct ImportArchive {
+    pub path: String,
+    pub conflict: ImportConflict,
+}
@@ -0,0 +1,82 @@
+use super::ImageService;
+use rinf::SignalPiece;
+use serde::{Deserialize, Serialize};
+
+/// How the image of a [`TimeSlot`] is chosen.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
+pub enum SlotImage {
+    /// Use the image at the slot's `path`.
+    Path,
+    /// Use the brightest cached image of the [`DayCycle::services`].
+    Brightest,
+    /// Use the darkest cached image of the [`DayCycle::services`].
+    Darkest,
+}
+
+/// A part of the day (e.g. "morning" or "night") that shows its own wallpaper.
+///
+/// A slot lasts until the next slot starts.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+pub struct TimeSlot {
+    pub name: String,
+    /// The hour (in local time, 24-hour clock) that the slot starts at.
+    pub hour: u8,
+    /// The minute (of the `hour`) that the slot starts at.
+    pub minute: u8,
+    pub image: SlotImage,
+    /// The path of the image used by [`SlotImage::Path`].
+    pub path: String,
+}
+
+/// How the [`DayCycle`] changes the wallpaper.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
+pub enum DayCycleOutput {
+    /// Set the slot's image as the wallpaper when each slot starts.
+    Switch,
+    /// Write a GNOME dynamic background (XML) that fades between the slots' images,
+    /// and set it as the wallpaper once.
+    ///
+    /// This only works on GNOME, but does not need the app to keep running.
+    GnomeXml,
+}
+
+/// Show different wallpapers at different times of the day.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+pub struct DayCycle {
+    pub enabled: bool,
+    pub slots: Vec<TimeSlot>,
+    /// The services whose cached images are considered for [`SlotImage::Brightest`]
+    /// and [`SlotImage::Darkest`].
+    pub services: Vec<ImageService>,
+    pub output: DayCycleOutput,
+    /// The number of seconds that a [`DayCycleOutput::GnomeXml`] background
+    /// takes to fade into the next slot's image.
+    pub transition: u32,
+}
+
+impl Default for DayCycle {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            slots: vec![
+                TimeSlot {
+                    name: "Light".to_string(),
+                    hour: 7,
+                    minute: 0,
+                    image: SlotImage::Brightest,
+                    path: String::new(),
+                },
+                TimeSlot {
+                    name: "Dark".to_string(),
+                    hour: 19,
+                    minute: 0,
+                    image: SlotImage::Darkest,
+                    path: String::new(),
+                },
+            ],
+            services: vec![ImageService::Bing],
+            output: DayCycleOutput::Switch,
+            transition: 1800,
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use super::ImageService;
+use rinf::{DartSignal, RustSignal};
+use serde::{Deserialize, Serialize};
+
+/// The recent refresh history of a service.
+///
+/// All times are formatted as RFC 3339.
+#[derive(Debug, Clone, Deserialize, RustSignal, Serialize)]
+pub struct ServiceHealth {
+    pub service: ImageService,
+    pub last_success: Option<String>,
+    pub last_failure: Option<String>,
+    /// The number of failed refreshes since the last successful one.
+    pub failures: u32,
+    /// The error that caused the last failure.
+    pub last_error: Option<String>,
+    /// Scheduled refreshes are skipped until this time (if any) because the service is failing.
+    pub retry_after: Option<String>,
+}
+
+impl ServiceHealth {
+    pub fn new(service: ImageService) -> Self {
+        Self {
+            service,
+            last_success: None,
+            last_failure: None,
+            failures: 0,
+            last_error: None,
+            retry_after: None,
+        }
+    }
+}
+
+/// Ask the hub to send the [`ServiceHealth`] of every service.
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct ServiceHealthRefresh;
@@ -0,0 +1,35 @@
+use super::{ImageService, WallpaperMode};
+use rinf::{DartSignal, RustSignal, SignalPiece};
+use serde::{Deserialize, Serialize};
+
+/// A successful change of the desktop wallpaper.
+#[derive(Debug, Clone, Deserialize, SignalPiece, Serialize)]
+pub struct WallpaperHistoryEntry {
+    /// When the wallpaper was set, formatted as RFC 3339.
+    pub set_at: String,
+    /// The service that the image came from, if it is a cached image.
+    pub service: Option<ImageService>,
+    /// The path of the selected image (before it was processed or captioned).
+    pub path: String,
+    pub mode: Option<WallpaperMode>,
+    /// The monitor that the wallpaper was set on, or `None` for all monitors.
+    pub monitor: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Ask the hub to send the [`WallpaperHistory`].
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct WallpaperHistoryRefresh;
+
+/// The recent changes of the desktop wallpaper (newest first).
+#[derive(Debug, RustSignal, Serialize)]
+pub struct WallpaperHistory {
+    pub entries: Vec<WallpaperHistoryEntry>,
+}
+
+/// Restore the wallpaper that was set before the current one.
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct RevertWallpaper {
+    /// The monitor to revert, or `None` to revert a wallpaper that was set on all monitors.
+    pub monitor: Option<String>,
+}
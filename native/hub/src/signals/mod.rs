@@ -4,6 +4,10 @@ use serde::Serialize;
 #[derive(Debug, SignalPiece, Serialize)]
 pub struct DailyImage {
     pub url: String,
+    /// The path to a downscaled copy of the image at `url`.
+    ///
+    /// This is empty if the thumbnail could not be generated.
+    pub thumbnail: String,
     pub date: String,
     pub description: String,
 }
@@ -16,3 +20,24 @@ pub use wallpaper::*;
 
 pub(crate) mod notifications;
 pub use notifications::*;
+
+pub(crate) mod schedule;
+pub use schedule::*;
+
+pub(crate) mod slideshow;
+pub use slideshow::*;
+
+pub(crate) mod health;
+pub use health::*;
+
+pub(crate) mod history;
+pub use history::*;
+
+pub(crate) mod day_cycle;
+pub use day_cycle::*;
+
+pub(crate) mod settings;
+pub use settings::*;
+
+pub(crate) mod archive;
+pub use archive::*;
@@ -1,4 +1,11 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use rinf::{DartSignal, RustSignal, SignalPiece};
 use serde::{Deserialize, Serialize};
@@ -8,7 +15,8 @@ pub struct NotificationRefresh;
 
 #[derive(Debug, DartSignal, Deserialize)]
 pub struct NotificationDismiss {
-    pub timestamp: String,
+    /// The [`NotificationAlert::id`] of the notification to dismiss.
+    pub id: u64,
 }
 
 #[derive(Debug, DartSignal, Deserialize)]
@@ -21,8 +29,24 @@ pub struct NotificationResults {
     pub just_finished: Vec<String>,
 }
 
+/// The ID returned by the next call of [`NotificationAlert::next_id()`].
+///
+/// This starts at the current time (in milliseconds),
+/// so IDs of saved notifications are not reused after a restart.
+static NEXT_ID: LazyLock<AtomicU64> = LazyLock::new(|| {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    AtomicU64::new(now.as_millis() as u64)
+});
+
 #[derive(Debug, SignalPiece, Deserialize, Serialize, Clone)]
 pub struct NotificationAlert {
+    /// Identifies the operation that the notification is about.
+    ///
+    /// Updates with the same ID replace the notification's state.
+    #[serde(default)]
+    pub id: u64,
     pub title: String,
     pub body: String,
     pub percent: f32,
@@ -31,7 +55,12 @@ pub struct NotificationAlert {
 }
 
 impl NotificationAlert {
-    /// Moves everything from `other` (except `title`) into self (overwriting current state).
+    /// Get a new (unique) notification ID, to be used for all updates of an operation.
+    pub fn next_id() -> u64 {
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Moves everything from `other` (except `id` and `title`) into self (overwriting current state).
     pub(crate) fn update(&mut self, other: Self) {
         self.body = other.body;
         self.percent = other.percent;
@@ -40,7 +69,7 @@ impl NotificationAlert {
     }
 }
 
-#[derive(Debug, SignalPiece, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, SignalPiece, Deserialize, Serialize, Clone, Copy, PartialEq)]
 pub enum NotificationSeverity {
     Debug,
     Info,
@@ -51,6 +80,43 @@ pub enum NotificationSeverity {
 #[derive(Debug, SignalPiece, Deserialize, Serialize, Clone, Default)]
 pub struct NotificationStatus {
     pub downloaded: Option<String>,
-    pub removed: Option<u8>,
+    pub removed: Option<u32>,
     pub elapsed: Option<String>,
 }
+
+/// Options to also show finished notifications on the desktop
+/// (through the freedesktop Notifications D-Bus interface).
+#[derive(Debug, SignalPiece, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DesktopNotifications {
+    pub enabled: bool,
+    /// Whether notifications of each severity are shown.
+    pub debug: bool,
+    pub info: bool,
+    pub warning: bool,
+    pub error: bool,
+}
+
+impl Default for DesktopNotifications {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            debug: false,
+            info: true,
+            warning: true,
+            error: true,
+        }
+    }
+}
+
+impl DesktopNotifications {
+    /// Check if notifications of the given `severity` are shown on the desktop.
+    pub fn includes(&self, severity: NotificationSeverity) -> bool {
+        self.enabled
+            && match severity {
+                NotificationSeverity::Debug => self.debug,
+                NotificationSeverity::Info => self.info,
+                NotificationSeverity::Warning => self.warning,
+                NotificationSeverity::Error => self.error,
+            }
+    }
+}
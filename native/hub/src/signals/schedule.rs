@@ -0,0 +1,27 @@
+use super::ImageService;
+use rinf::SignalPiece;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+pub struct RefreshSchedule {
+    pub enabled: bool,
+    /// The hour (in local time, 24-hour clock) to run at.
+    pub hour: u8,
+    /// The minute (of the `hour`) to run at.
+    pub minute: u8,
+    /// The services to refresh.
+    ///
+    /// The newest image from the first successfully refreshed service becomes the wallpaper.
+    pub services: Vec<ImageService>,
+}
+
+impl Default for RefreshSchedule {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hour: 8,
+            minute: 0,
+            services: vec![ImageService::Bing],
+        }
+    }
+}
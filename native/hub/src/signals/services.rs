@@ -2,7 +2,7 @@ use super::DailyImage;
 use rinf::{DartSignal, RustSignal, SignalPiece};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy)]
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ImageService {
     Bing,
     Nasa,
@@ -10,6 +10,8 @@ pub enum ImageService {
 }
 
 impl ImageService {
+    pub const ALL: [Self; 3] = [Self::Bing, Self::Nasa, Self::Spotlight];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             ImageService::Bing => "Bing",
@@ -27,12 +29,23 @@ impl ImageService {
     }
 }
 
+/// Whether a service is used, at its place in the services' display order.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
+pub struct ServiceEntry {
+    pub service: ImageService,
+    pub enabled: bool,
+}
+
 #[derive(Debug, DartSignal, Deserialize, Serialize)]
 pub struct Refresh {
     pub service: ImageService,
     pub reset: bool,
 }
 
+/// Scan all cached images for damaged files and repair or remove them.
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct VerifyCache;
+
 #[derive(Debug, RustSignal, Serialize)]
 pub struct ImageList {
     pub service: ImageService,
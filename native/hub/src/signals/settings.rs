@@ -0,0 +1,67 @@
+use super::{
+    Caption, DayCycle, DesktopNotifications, ImageProcessing, RefreshSchedule, ServiceEntry,
+    Slideshow, WallpaperBackendConfig, WallpaperMode,
+};
+use rinf::{DartSignal, RustSignal, SignalPiece};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Options for the connections to the image services.
+#[derive(Debug, Default, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+pub struct NetworkOptions {
+    /// The number of seconds to wait for a connection (or for more data)
+    /// before a request is abandoned, or 0 for no limit.
+    pub timeout: u32,
+    /// The URL of a proxy used for all requests (e.g. `socks5://localhost:1080`).
+    ///
+    /// If empty, then the system's proxy configuration is used.
+    pub proxy: String,
+}
+
+/// All of the app's persistent settings.
+///
+/// Fields that are missing from the settings file get their default values.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// The version of the settings file's layout, used to migrate older files.
+    pub version: u32,
+    pub mode: WallpaperMode,
+    pub schedule: RefreshSchedule,
+    pub slideshow: Slideshow,
+    /// The image last set on each monitor (by name),
+    /// for monitors that show a different image than the others.
+    /// These are set again when the app (or the daemon) starts.
+    pub monitors: BTreeMap<String, String>,
+    pub processing: ImageProcessing,
+    pub caption: Caption,
+    pub backend: WallpaperBackendConfig,
+    pub day_cycle: DayCycle,
+    pub network: NetworkOptions,
+    /// The services in their display order, and whether each is enabled.
+    ///
+    /// Services that are not listed are enabled (after the listed ones).
+    pub services: Vec<ServiceEntry>,
+    pub desktop_notifications: DesktopNotifications,
+    /// How many times per second (at most) the progress of operations is sent to the UI,
+    /// or 0 for no limit.
+    pub notification_rate: u32,
+}
+
+/// Ask the hub to send the [`SettingsState`].
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct GetSettings;
+
+/// Replace all settings.
+///
+/// The hub responds with the [`SettingsState`].
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct UpdateSettings {
+    pub settings: Settings,
+}
+
+/// The current settings, sent whenever they change.
+#[derive(Debug, RustSignal, Serialize)]
+pub struct SettingsState {
+    pub settings: Settings,
+}
@@ -0,0 +1,61 @@
+use super::ImageService;
+use rinf::{DartSignal, RustSignal, SignalPiece};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
+pub enum SlideshowOrder {
+    /// Cycle through images from newest to oldest.
+    Date,
+    /// Cycle through all images in a random order.
+    Shuffle,
+    /// Pick images randomly, favoring the most recent ones.
+    Recent,
+}
+
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+pub struct Slideshow {
+    pub enabled: bool,
+    /// The number of minutes between wallpaper changes.
+    pub interval: u32,
+    /// The services whose cached images are shown.
+    pub services: Vec<ImageService>,
+    pub order: SlideshowOrder,
+    /// Show a different image on each monitor (where the platform allows it).
+    #[serde(default)]
+    pub per_monitor: bool,
+}
+
+impl Default for Slideshow {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 30,
+            services: vec![ImageService::Bing],
+            order: SlideshowOrder::Date,
+            per_monitor: false,
+        }
+    }
+}
+
+/// Start the slideshow.
+///
+/// If `slideshow` is `None`, then the last used slideshow settings are resumed.
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct StartSlideshow {
+    pub slideshow: Option<Slideshow>,
+}
+
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct StopSlideshow;
+
+/// Get the slideshow's current state.
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct SlideshowRefresh;
+
+/// The slideshow's current state, sent whenever it changes.
+#[derive(Debug, RustSignal, Serialize)]
+pub struct SlideshowState {
+    pub slideshow: Slideshow,
+    /// The path of the image currently shown by the slideshow (if any).
+    pub current: Option<String>,
+}
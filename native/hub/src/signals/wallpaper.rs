@@ -1,13 +1,15 @@
 use rinf::{DartSignal, RustSignal, SignalPiece};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy)]
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
 pub enum WallpaperMode {
     Center = 0,
     Crop = 1,
     Fit = 2,
     Stretch = 3,
     Tile = 4,
+    /// Pick one of the other modes based on the image's size and the screen's aspect ratio.
+    Auto = 5,
 }
 
 #[derive(Debug, DartSignal, RustSignal, Deserialize, Serialize)]
@@ -15,11 +17,119 @@ pub struct WallpaperModeCache {
     pub mode: Option<WallpaperMode>,
 }
 
+/// How an image is cropped to the screen's aspect ratio.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
+pub enum CropStrategy {
+    /// Keep the middle of the image.
+    Center,
+    /// Keep the most detailed part of the image.
+    Saliency,
+}
+
+/// Options to resize and crop images to the screen before they are set as the wallpaper.
+///
+/// This only applies to the [`WallpaperMode::Crop`] and [`WallpaperMode::Fit`] modes.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+pub struct ImageProcessing {
+    pub enabled: bool,
+    /// The target width in pixels.
+    ///
+    /// If this or the `height` is 0, then the monitor's resolution is used.
+    pub width: u32,
+    /// The target height in pixels.
+    pub height: u32,
+    pub crop: CropStrategy,
+}
+
+impl Default for ImageProcessing {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 0,
+            height: 0,
+            crop: CropStrategy::Center,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
+pub enum CaptionCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Options to render the image's description onto the wallpaper.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+pub struct Caption {
+    pub enabled: bool,
+    pub corner: CaptionCorner,
+    /// The height of the text in pixels, relative to a 1080 pixel tall image.
+    pub font_size: u32,
+    /// The text colour as ARGB (e.g. `0xFFFFFFFF` for opaque white).
+    ///
+    /// The caption's background is a semi-transparent contrasting colour.
+    pub color: u32,
+    /// The path to a TrueType/OpenType font file.
+    ///
+    /// If empty, then a common system font is used.
+    pub font: String,
+}
+
+impl Default for Caption {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            corner: CaptionCorner::BottomRight,
+            font_size: 20,
+            color: 0xFFFFFFFF,
+            font: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, Copy, PartialEq)]
+pub enum WallpaperBackendKind {
+    /// Use the desktop environment's own wallpaper setting.
+    Desktop,
+    /// Run the configured [`WallpaperBackendConfig::command`].
+    Command,
+    /// Only record what would be set to the [`WallpaperBackendConfig::log`] file.
+    DryRun,
+}
+
+/// How the wallpaper is applied to the desktop.
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone, PartialEq)]
+pub struct WallpaperBackendConfig {
+    pub kind: WallpaperBackendKind,
+    /// The command used by [`WallpaperBackendKind::Command`] (e.g. `swaybg -i {path} -m {mode}`).
+    ///
+    /// The `{path}`, `{mode}` and `{monitor}` placeholders are replaced in each argument.
+    pub command: String,
+    /// The file that [`WallpaperBackendKind::DryRun`] appends its calls to.
+    pub log: String,
+}
+
+impl Default for WallpaperBackendConfig {
+    fn default() -> Self {
+        Self {
+            kind: WallpaperBackendKind::Desktop,
+            command: String::new(),
+            log: String::new(),
+        }
+    }
+}
+
 #[cfg(not(target_os = "android"))]
-#[derive(Debug, Deserialize, SignalPiece)]
+#[derive(Debug, Clone, Deserialize, SignalPiece)]
 pub struct WallpaperSelection {
     pub path: String,
     pub mode: Option<WallpaperMode>,
+    /// The name of the monitor to set the wallpaper on.
+    ///
+    /// If `None`, then the wallpaper is set on all monitors.
+    pub monitor: Option<String>,
 }
 
 #[cfg(not(target_os = "android"))]
@@ -27,3 +137,30 @@ pub struct WallpaperSelection {
 pub struct SetWallpaper {
     pub selected: WallpaperSelection,
 }
+
+#[cfg(not(target_os = "android"))]
+#[derive(Debug, Deserialize, SignalPiece, Serialize, Clone)]
+pub struct Monitor {
+    pub name: String,
+    /// The width of the monitor in pixels (0 if unknown).
+    pub width: u32,
+    /// The height of the monitor in pixels (0 if unknown).
+    pub height: u32,
+    pub primary: bool,
+    /// The path of the image last set on only this monitor (if any).
+    pub wallpaper: Option<String>,
+}
+
+/// Get the monitors connected to the desktop.
+#[cfg(not(target_os = "android"))]
+#[derive(Debug, DartSignal, Deserialize)]
+pub struct ListMonitors;
+
+/// The response to [`ListMonitors`].
+///
+/// This is empty if the platform does not allow listing monitors.
+#[cfg(not(target_os = "android"))]
+#[derive(Debug, RustSignal, Serialize)]
+pub struct MonitorList {
+    pub monitors: Vec<Monitor>,
+}
@@ -0,0 +1,557 @@
+//! The slideshow periodically changes the desktop wallpaper
+//! to another image from the cache of one or more services.
+#![cfg(not(target_os = "android"))]
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    common::check_err,
+    notification_center::{NotificationActor, NotificationUpdate},
+    services::{list_all_cached_images, settings::SettingsChanged},
+    set_wallpaper::WallpaperActor,
+    settings_center::{edit_settings, get_settings, SettingsActor},
+    signals::{
+        ListMonitors, NotificationAlert, NotificationSeverity, NotificationStatus, SetWallpaper,
+        Slideshow, SlideshowOrder, SlideshowRefresh, SlideshowState, StartSlideshow, StopSlideshow,
+        WallpaperSelection,
+    },
+};
+use anyhow::{anyhow, Result};
+use messages::prelude::{async_trait, Actor, Address, Context as MsgContext, Handler, Notifiable};
+use rand::{
+    distr::{weighted::WeightedIndex, Distribution},
+    seq::SliceRandom,
+};
+use rinf::{debug_print, DartSignal, RustSignal};
+use tokio::{
+    spawn,
+    task::JoinHandle,
+    time::{interval_at, Instant},
+};
+
+/// The maximum number of previously shown images remembered by the slideshow.
+const MAX_HISTORY: usize = 20;
+
+/// Tells the [`SlideshowActor`] to show the next image.
+pub struct NextSlide;
+
+/// Tells the [`SlideshowActor`] to show the previously shown image again.
+pub struct PreviousSlide;
+
+/// Pick the next image to show from `images` (ordered newest first).
+///
+/// The `queue` holds the remaining images for the current [`SlideshowOrder::Shuffle`] cycle.
+fn pick_next(
+    images: &[PathBuf],
+    order: SlideshowOrder,
+    current: Option<&Path>,
+    queue: &mut Vec<PathBuf>,
+) -> Option<PathBuf> {
+    if images.is_empty() {
+        return None;
+    }
+    let mut rng = rand::rng();
+    match order {
+        SlideshowOrder::Date => {
+            let next = current
+                .and_then(|current| images.iter().position(|img| img == current))
+                .map(|i| (i + 1) % images.len())
+                .unwrap_or_default();
+            Some(images[next].clone())
+        }
+        SlideshowOrder::Shuffle => {
+            // skip queued images that are no longer cached
+            queue.retain(|img| images.contains(img));
+            if queue.is_empty() {
+                *queue = images.to_vec();
+                queue.shuffle(&mut rng);
+                // avoid showing the same image twice in a row when starting a new cycle
+                if queue.len() > 1 && queue.last().map(PathBuf::as_path) == current {
+                    let last = queue.len() - 1;
+                    queue.swap(0, last);
+                }
+            }
+            queue.pop()
+        }
+        SlideshowOrder::Recent => {
+            // the newest image is the most likely to be picked; the oldest is the least likely
+            let weights = (1..=images.len()).rev();
+            let dist = WeightedIndex::new(weights).ok()?;
+            Some(images[dist.sample(&mut rng)].clone())
+        }
+    }
+}
+
+pub struct SlideshowActor {
+    app_cache_dir: PathBuf,
+    slideshow_addr: Address<Self>,
+    wallpaper: Address<WallpaperActor>,
+    notification_center: Address<NotificationActor>,
+    settings: Address<SettingsActor>,
+    slideshow: Slideshow,
+    current: Option<PathBuf>,
+    /// The previously shown images (most recent last).
+    history: Vec<PathBuf>,
+    queue: Vec<PathBuf>,
+    timer: Option<JoinHandle<()>>,
+}
+
+impl Actor for SlideshowActor {}
+
+impl SlideshowActor {
+    pub fn new(
+        slideshow_addr: Address<Self>,
+        app_cache_dir: PathBuf,
+        slideshow: Slideshow,
+        wallpaper: Address<WallpaperActor>,
+        notification_center: Address<NotificationActor>,
+        settings: Address<SettingsActor>,
+    ) -> Self {
+        spawn(Self::listen_to_start(slideshow_addr.clone()));
+        spawn(Self::listen_to_stop(slideshow_addr.clone()));
+        spawn(Self::listen_to_refresh(slideshow_addr.clone()));
+        Self {
+            app_cache_dir,
+            slideshow_addr,
+            wallpaper,
+            notification_center,
+            settings,
+            slideshow,
+            current: None,
+            history: vec![],
+            queue: vec![],
+            timer: None,
+        }
+    }
+
+    async fn listen_to_start(mut slideshow_addr: Address<Self>) {
+        let receiver = StartSlideshow::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            debug_print!("starting slideshow");
+            if let Ok(result) = check_err(
+                slideshow_addr
+                    .send(dart_signal.message)
+                    .await
+                    .map_err(|e| anyhow!("Failed to start slideshow: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+        }
+    }
+
+    async fn listen_to_stop(mut slideshow_addr: Address<Self>) {
+        let receiver = StopSlideshow::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            debug_print!("stopping slideshow");
+            if let Ok(result) = check_err(
+                slideshow_addr
+                    .send(dart_signal.message)
+                    .await
+                    .map_err(|e| anyhow!("Failed to stop slideshow: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+        }
+    }
+
+    async fn listen_to_refresh(mut slideshow_addr: Address<Self>) {
+        let receiver = SlideshowRefresh::get_dart_signal_receiver();
+        while let Some(dart_signal) = receiver.recv().await {
+            let _ = slideshow_addr.send(dart_signal.message).await;
+        }
+    }
+
+    /// (Re)start the timer that triggers wallpaper changes.
+    ///
+    /// If the slideshow is disabled, then the timer is only stopped.
+    fn restart_timer(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            timer.abort();
+        }
+        if self.slideshow.enabled {
+            let period = Duration::from_secs(u64::from(self.slideshow.interval.max(1)) * 60);
+            self.timer = Some(spawn(Self::run_timer(self.slideshow_addr.clone(), period)));
+        }
+    }
+
+    async fn run_timer(mut slideshow_addr: Address<Self>, period: Duration) {
+        let mut timer = interval_at(Instant::now() + period, period);
+        loop {
+            timer.tick().await;
+            if let Ok(result) = check_err(
+                slideshow_addr
+                    .send(NextSlide)
+                    .await
+                    .map_err(|e| anyhow!("Failed to change slideshow image: {e:?}")),
+            ) {
+                let _ = check_err(result);
+            }
+        }
+    }
+
+    async fn save_settings(&mut self) -> Result<()> {
+        let slideshow = self.slideshow.clone();
+        edit_settings(&mut self.settings, |settings| {
+            settings.slideshow = slideshow;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    fn get_state(&self) -> SlideshowState {
+        SlideshowState {
+            slideshow: self.slideshow.clone(),
+            current: self
+                .current
+                .as_ref()
+                .map(|p| p.to_string_lossy().to_string()),
+        }
+    }
+
+    fn send_state(&self) {
+        self.get_state().send_signal_to_dart();
+    }
+
+    async fn notify(&mut self, notification: NotificationAlert) {
+        if let Ok(result) = check_err(
+            self.notification_center
+                .send(NotificationUpdate(notification))
+                .await
+                .map_err(|e| anyhow!("Failed to send notification: {e:?}")),
+        ) {
+            let _ = check_err(result);
+        }
+    }
+
+    /// Set the wallpaper to the next image in the slideshow.
+    async fn show_next(&mut self) -> Result<()> {
+        let settings = get_settings(&mut self.settings).await?;
+        let services: Vec<_> = self
+            .slideshow
+            .services
+            .iter()
+            .filter(|s| settings.is_service_enabled(**s))
+            .copied()
+            .collect();
+        let images = list_all_cached_images(&self.app_cache_dir, &services).await?;
+        if self.slideshow.per_monitor {
+            let monitors = self.get_monitors().await;
+            if monitors.len() > 1 {
+                return self.show_next_per_monitor(&images, &monitors).await;
+            }
+        }
+        let Some(next) = pick_next(
+            &images,
+            self.slideshow.order,
+            self.current.as_deref(),
+            &mut self.queue,
+        ) else {
+            debug_print!("No cached images to show in the slideshow");
+            return Ok(());
+        };
+        if self.apply(&next, None).await? {
+            self.set_current(next);
+        }
+        Ok(())
+    }
+
+    /// Get the names of the monitors connected to the desktop.
+    ///
+    /// This is empty if the platform does not allow listing monitors.
+    async fn get_monitors(&mut self) -> Vec<String> {
+        let monitors = self
+            .wallpaper
+            .send(ListMonitors)
+            .await
+            .map_err(|e| anyhow!("Failed to list monitors: {e:?}"));
+        check_err(monitors)
+            .and_then(check_err)
+            .map(|monitors| monitors.into_iter().map(|m| m.name).collect())
+            .unwrap_or_default()
+    }
+
+    /// Set a different image from `images` on each of the `monitors`.
+    async fn show_next_per_monitor(
+        &mut self,
+        images: &[PathBuf],
+        monitors: &[String],
+    ) -> Result<()> {
+        let mut shown: Vec<PathBuf> = vec![];
+        for monitor in monitors {
+            let current = shown.last().or(self.current.as_ref()).cloned();
+            let mut next = None;
+            // random orders may pick an image that is already shown on another monitor
+            for _ in 0..3 {
+                next = pick_next(
+                    images,
+                    self.slideshow.order,
+                    current.as_deref(),
+                    &mut self.queue,
+                );
+                if next.as_ref().is_none_or(|next| !shown.contains(next)) {
+                    break;
+                }
+            }
+            let Some(next) = next else {
+                debug_print!("No cached images to show in the slideshow");
+                return Ok(());
+            };
+            if self.apply(&next, Some(monitor)).await? {
+                shown.push(next);
+            }
+        }
+        if let Some(last) = shown.pop() {
+            self.set_current(last);
+        }
+        Ok(())
+    }
+
+    /// Remember the `image` as the one currently shown.
+    fn set_current(&mut self, image: PathBuf) {
+        if let Some(previous) = self.current.replace(image) {
+            self.history.push(previous);
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.send_state();
+    }
+
+    /// Set the wallpaper to the image shown before the current one.
+    async fn show_previous(&mut self) -> Result<()> {
+        while let Some(previous) = self.history.pop() {
+            // skip images that are no longer cached
+            if !previous.exists() {
+                continue;
+            }
+            if self.apply(&previous, None).await? {
+                self.current = Some(previous);
+                self.send_state();
+            }
+            return Ok(());
+        }
+        debug_print!("No previous image to show in the slideshow");
+        Ok(())
+    }
+
+    /// Set the wallpaper to the given `image` on a `monitor` (or all monitors if `None`).
+    ///
+    /// Returns `false` if the wallpaper could not be set (the error is reported as a notification).
+    async fn apply(&mut self, image: &Path, monitor: Option<&str>) -> Result<bool> {
+        let mode = get_settings(&mut self.settings).await?.mode;
+        debug_print!("Slideshow changing wallpaper to {image:?} (monitor: {monitor:?})");
+        let result = self
+            .wallpaper
+            .send(SetWallpaper {
+                selected: WallpaperSelection {
+                    path: image.to_string_lossy().to_string(),
+                    mode: Some(mode),
+                    monitor: monitor.map(str::to_string),
+                },
+            })
+            .await
+            .map_err(|e| anyhow!("{e:?}"));
+        if let Ok(Err(e)) | Err(e) = result {
+            self.notify(NotificationAlert {
+                id: NotificationAlert::next_id(),
+                title: "Slideshow".to_string(),
+                body: format!("Failed to set the desktop wallpaper: {e:?}"),
+                percent: 1.0,
+                severity: NotificationSeverity::Error,
+                status: NotificationStatus::default(),
+            })
+            .await;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl Handler<StartSlideshow> for SlideshowActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, msg: StartSlideshow, _context: &MsgContext<Self>) -> Self::Result {
+        if let Some(slideshow) = msg.slideshow {
+            if slideshow.order != self.slideshow.order {
+                self.queue.clear();
+            }
+            self.slideshow = slideshow;
+        }
+        self.slideshow.enabled = true;
+        self.save_settings().await?;
+        self.restart_timer();
+        self.send_state();
+        self.show_next().await
+    }
+}
+
+#[async_trait]
+impl Notifiable<SettingsChanged> for SlideshowActor {
+    async fn notify(&mut self, msg: SettingsChanged, _context: &MsgContext<Self>) {
+        let slideshow = msg.new.slideshow;
+        if slideshow == self.slideshow {
+            return;
+        }
+        if slideshow.order != self.slideshow.order {
+            self.queue.clear();
+        }
+        self.slideshow = slideshow;
+        self.restart_timer();
+        self.send_state();
+    }
+}
+
+#[async_trait]
+impl Handler<StopSlideshow> for SlideshowActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, _msg: StopSlideshow, _context: &MsgContext<Self>) -> Self::Result {
+        self.slideshow.enabled = false;
+        self.save_settings().await?;
+        self.restart_timer();
+        self.send_state();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Handler<SlideshowRefresh> for SlideshowActor {
+    type Result = SlideshowState;
+
+    async fn handle(
+        &mut self,
+        _msg: SlideshowRefresh,
+        _context: &MsgContext<Self>,
+    ) -> Self::Result {
+        self.send_state();
+        self.get_state()
+    }
+}
+
+#[async_trait]
+impl Handler<NextSlide> for SlideshowActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, _msg: NextSlide, _context: &MsgContext<Self>) -> Self::Result {
+        self.show_next().await
+    }
+}
+
+#[async_trait]
+impl Handler<PreviousSlide> for SlideshowActor {
+    type Result = Result<()>;
+
+    async fn handle(&mut self, _msg: PreviousSlide, _context: &MsgContext<Self>) -> Self::Result {
+        self.show_previous().await
+    }
+}
+
+// Creates and spawns the actors in the async system.
+pub async fn create_actors(
+    app_cache_dir: PathBuf,
+    wallpaper: Address<WallpaperActor>,
+    notification_center: Address<NotificationActor>,
+    mut settings: Address<SettingsActor>,
+) -> Result<Address<SlideshowActor>> {
+    // Create actor contexts.
+    let slideshow_context = MsgContext::new();
+    let slideshow_addr = slideshow_context.address();
+
+    // Spawn actors.
+    let slideshow = get_settings(&mut settings).await?.slideshow;
+    let mut actor = SlideshowActor::new(
+        slideshow_addr.clone(),
+        app_cache_dir,
+        slideshow,
+        wallpaper,
+        notification_center,
+        settings,
+    );
+    // resume the slideshow if it was running when the app last closed
+    actor.restart_timer();
+    spawn(slideshow_context.run(actor));
+    Ok(slideshow_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pick_next;
+    use crate::signals::SlideshowOrder;
+    use std::path::PathBuf;
+
+    fn images(count: usize) -> Vec<PathBuf> {
+        (0..count)
+            .map(|i| PathBuf::from(format!("/cache/{i}.jpg")))
+            .collect()
+    }
+
+    #[test]
+    fn date_order_cycles_from_newest() {
+        let images = images(3);
+        let mut queue = vec![];
+        let next = |current| pick_next(&images, SlideshowOrder::Date, current, &mut vec![]);
+        assert_eq!(next(None), Some(images[0].clone()));
+        assert_eq!(next(Some(images[0].as_path())), Some(images[1].clone()));
+        assert_eq!(next(Some(images[2].as_path())), Some(images[0].clone()));
+        // an image that is no longer cached starts over
+        let gone = PathBuf::from("/cache/gone.jpg");
+        assert_eq!(next(Some(gone.as_path())), Some(images[0].clone()));
+        assert_eq!(pick_next(&[], SlideshowOrder::Date, None, &mut queue), None);
+    }
+
+    #[test]
+    fn shuffle_shows_every_image_once_per_cycle() {
+        let images = images(5);
+        let mut queue = vec![];
+        let mut current: Option<PathBuf> = None;
+        for _ in 0..10 {
+            let mut cycle = vec![];
+            for _ in 0..images.len() {
+                let next = pick_next(
+                    &images,
+                    SlideshowOrder::Shuffle,
+                    current.as_deref(),
+                    &mut queue,
+                )
+                .unwrap();
+                assert_ne!(Some(&next), current.as_ref());
+                cycle.push(next.clone());
+                current = Some(next);
+            }
+            cycle.sort();
+            assert_eq!(cycle, images);
+        }
+    }
+
+    #[test]
+    fn shuffle_skips_images_that_are_no_longer_cached() {
+        let all = images(4);
+        let mut queue = vec![];
+        pick_next(&all, SlideshowOrder::Shuffle, None, &mut queue);
+        let remaining = &all[..2];
+        for _ in 0..10 {
+            let next = pick_next(remaining, SlideshowOrder::Shuffle, None, &mut queue).unwrap();
+            assert!(remaining.contains(&next));
+        }
+    }
+
+    #[test]
+    fn recent_order_prefers_newer_images() {
+        let images = images(4);
+        let mut counts = [0; 4];
+        for _ in 0..4000 {
+            let next = pick_next(&images, SlideshowOrder::Recent, None, &mut vec![]).unwrap();
+            counts[images.iter().position(|img| *img == next).unwrap()] += 1;
+        }
+        // the weights are 4:3:2:1
+        assert!(
+            counts.windows(2).all(|pair| pair[0] > pair[1]),
+            "{counts:?}"
+        );
+    }
+}